@@ -1,10 +1,13 @@
 #!/bin/bash
 
-# This script expects SITE_URL and DATABASE to be defined in the environment
+# This script expects SITE_URL and DATABASE to be defined in the environment.
+# When running several collectors, set COLLECTOR to a name unique to each.
 
 set -u -o pipefail
 
-echo "Running collector targeting $SITE_URL"
+COLLECTOR=${COLLECTOR:-$(hostname)}
+
+echo "Running collector $COLLECTOR targeting $SITE_URL"
 
 export RUST_LOG=collector=trace,collector::sysroot=debug
 export PATH="/home/collector/.cargo/bin:$PATH"
@@ -23,6 +26,6 @@ while : ; do
         rm todo-artifacts
         touch todo-artifacts
 
-        target/release/collector bench_next $SITE_URL --self-profile --db $DATABASE;
+        target/release/collector bench_next $SITE_URL --self-profile --db $DATABASE --collector $COLLECTOR;
         echo finished run at `date`;
 done
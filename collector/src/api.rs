@@ -1,14 +1,56 @@
 pub mod next_commit {
+    /// The names accepted in `Commit::builds`; these match the collector's
+    /// `--builds` argument.
+    pub const BUILD_KINDS: &[&str] = &["Check", "Debug", "Doc", "Opt", "All"];
+
+    /// The names accepted in `Commit::scenarios`; these match the collector's
+    /// `--runs` argument.
+    pub const SCENARIOS: &[&str] = &["Full", "IncrFull", "IncrUnchanged", "IncrPatched", "All"];
+
     #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
     pub struct Commit {
         pub sha: String,
         pub include: Option<String>,
         pub exclude: Option<String>,
         pub runs: Option<i32>,
+        /// Comma-separated build kinds; all of them if not set.
+        pub builds: Option<String>,
+        /// Comma-separated scenarios; all of them if not set.
+        pub scenarios: Option<String>,
     }
 
+    /// A queued artifact, e.g. a release.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct Artifact {
+        /// The name to record the results under; also the rustup toolchain
+        /// to benchmark unless `local_rustc` is set.
+        pub name: String,
+        /// The path to a rustc on the collector machine.
+        pub local_rustc: Option<String>,
+    }
+
+    /// At most one of `commit` and `artifact` is set.
     #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
     pub struct Response {
         pub commit: Option<Commit>,
+        pub artifact: Option<Artifact>,
+    }
+}
+
+pub mod collector_heartbeat {
+    /// Sent periodically by a collector while it benchmarks `sha`, and once
+    /// more with `finished` set when it is done.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct Request {
+        pub collector: String,
+        pub sha: String,
+        pub finished: bool,
+    }
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct Response {
+        /// False if the collector's lease on `sha` expired and the commit may
+        /// have been handed to another collector.
+        pub lease_held: bool,
     }
 }
@@ -6,6 +6,7 @@ use collector::command_output;
 use database::{PatchName, QueryLabel};
 use futures::stream::FuturesUnordered;
 use futures::stream::StreamExt;
+use sha2::{Digest, Sha256};
 use std::cmp;
 use std::collections::HashMap;
 use std::env;
@@ -1093,6 +1094,39 @@ impl Benchmark {
         self.config.supports_stable
     }
 
+    /// Hashes the contents of the benchmark directory, including the patches
+    /// and `perf-config.json`, so that changes to the benchmark itself can be
+    /// told apart from changes to the compiler.
+    pub fn source_hash(&self) -> anyhow::Result<String> {
+        fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+            for entry in fs::read_dir(dir).with_context(|| format!("failed to list {:?}", dir))? {
+                let path = entry?.path();
+                // Follow symlinks, like `make_temp_dir` does.
+                if fs::metadata(&path)?.is_dir() {
+                    collect_files(&path, files)?;
+                } else {
+                    files.push(path);
+                }
+            }
+            Ok(())
+        }
+
+        let mut files = Vec::new();
+        collect_files(&self.path, &mut files)?;
+        files.sort();
+
+        let mut hasher = Sha256::new();
+        for file in files {
+            let contents = fs::read(&file).with_context(|| format!("failed to read {:?}", file))?;
+            let name = file.strip_prefix(&self.path).unwrap().to_string_lossy();
+            hasher.input(name.as_bytes());
+            hasher.input(&[0]);
+            hasher.input(&(contents.len() as u64).to_le_bytes());
+            hasher.input(&contents);
+        }
+        Ok(hex::encode(hasher.result()))
+    }
+
     fn make_temp_dir(&self, base: &Path) -> anyhow::Result<TempDir> {
         // Appending `.` means we copy just the contents of `base` into
         // `tmp_dir`, rather than `base` itself.
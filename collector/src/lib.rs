@@ -97,6 +97,12 @@ where
     Ok(Option::deserialize(deserializer)?.unwrap_or(0.0))
 }
 
+/// Whether `name` is a toolchain which `bench_next` can install with rustup,
+/// i.e. a release like `1.47.0` or a beta.
+pub fn is_published_toolchain(name: &str) -> bool {
+    name.parse::<semver::Version>().is_ok() || name == "beta" || name.starts_with("beta-")
+}
+
 pub fn version_supports_doc(version_str: &str) -> bool {
     if let Some(version) = version_str.parse::<semver::Version>().ok() {
         version >= semver::Version::new(1, 46, 0)
@@ -12,6 +12,10 @@ use std::io::{stderr, Write};
 use std::path::{Path, PathBuf};
 use std::process;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
 use std::{str, time::Instant};
 use tokio::runtime::Runtime;
 
@@ -40,6 +44,43 @@ impl<'a> Compiler<'a> {
             is_nightly: true,
         }
     }
+
+    /// Queries `rustc -vV` for the version details of this compiler.
+    fn metadata(&self) -> anyhow::Result<database::CompilerMetadata> {
+        let output = Command::new(self.rustc)
+            .arg("-vV")
+            .output()
+            .context("failed to run rustc -vV")?;
+        if !output.status.success() {
+            bail!("rustc -vV failed: {:?}", output);
+        }
+        let stdout = String::from_utf8(output.stdout).context("rustc -vV output is not utf8")?;
+
+        let mut lines = stdout.lines();
+        let version = lines.next().context("empty rustc -vV output")?.to_string();
+        let mut commit_hash = None;
+        let mut llvm_version = None;
+        let mut host = None;
+        for line in lines {
+            let (key, value) = match line.find(':') {
+                Some(idx) => (&line[..idx], line[idx + 1..].trim()),
+                None => continue,
+            };
+            match key {
+                "commit-hash" if value != "unknown" => commit_hash = Some(value.to_string()),
+                "LLVM version" => llvm_version = Some(value.to_string()),
+                "host" => host = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(database::CompilerMetadata {
+            version,
+            commit_hash,
+            llvm_version,
+            host: host.context("no host in rustc -vV output")?,
+        })
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -164,6 +205,76 @@ fn n_benchmarks_remaining(n: usize) -> String {
     format!("{} benchmark{} remaining", n, suffix)
 }
 
+/// How often `bench_next` tells the site that it is still working on its
+/// commit. The site hands the commit to another collector if the heartbeats
+/// stop for long enough.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sends heartbeats for a commit to the site from a background thread, as the
+/// benchmarks themselves block the main thread.
+struct Heartbeat {
+    stop: mpsc::Sender<()>,
+    thread: thread::JoinHandle<()>,
+    lost: Arc<AtomicBool>,
+}
+
+impl Heartbeat {
+    fn start(
+        client: reqwest::blocking::Client,
+        site_url: &str,
+        collector: &str,
+        sha: &str,
+    ) -> Heartbeat {
+        let url = format!("{}/perf/collector_heartbeat", site_url);
+        let mut request = collector::api::collector_heartbeat::Request {
+            collector: collector.to_string(),
+            sha: sha.to_string(),
+            finished: false,
+        };
+        let (stop, stopped) = mpsc::channel();
+        let lost = Arc::new(AtomicBool::new(false));
+        let lease_lost = lost.clone();
+        let thread = thread::spawn(move || loop {
+            request.finished = match stopped.recv_timeout(HEARTBEAT_INTERVAL) {
+                Ok(()) => true,
+                Err(mpsc::RecvTimeoutError::Timeout) => false,
+                // Dropped without finishing, e.g. because the site couldn't be
+                // told about the results; let the lease expire instead.
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            let response = client
+                .post(&url)
+                .json(&request)
+                .send()
+                .and_then(|r| r.json::<collector::api::collector_heartbeat::Response>());
+            match response {
+                Ok(response) if !response.lease_held => {
+                    log::warn!("lost the lease on {}", request.sha);
+                    lease_lost.store(true, Ordering::SeqCst);
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("failed to send heartbeat: {:?}", e),
+            }
+            if request.finished {
+                break;
+            }
+        });
+        Heartbeat { stop, thread, lost }
+    }
+
+    /// Whether the site has handed the commit to another collector, in which
+    /// case benchmarking it should stop.
+    fn lost(&self) -> bool {
+        self.lost.load(Ordering::SeqCst)
+    }
+
+    /// Stops the heartbeats, releasing the commit.
+    fn finish(self) {
+        let _ = self.stop.send(());
+        let _ = self.thread.join();
+    }
+}
+
 struct BenchmarkErrors(usize);
 
 impl BenchmarkErrors {
@@ -193,6 +304,7 @@ fn bench(
     benchmarks: &[Benchmark],
     iterations: Option<usize>,
     self_profile: bool,
+    lease: Option<&Heartbeat>,
 ) -> BenchmarkErrors {
     let mut conn = rt.block_on(pool.connection());
     let mut errors = BenchmarkErrors::new();
@@ -212,12 +324,20 @@ fn bench(
         .map(|b| b.name.to_string())
         .collect::<Vec<_>>();
 
+    let metadata = compiler
+        .metadata()
+        .map_err(|e| log::warn!("failed to get compiler metadata: {:?}", e))
+        .ok();
+
     // Make sure there is no observable time when the artifact ID is available
     // but the in-progress steps are not.
     let interned_cid = {
         let mut tx = rt.block_on(conn.transaction());
         let interned_cid = rt.block_on(tx.conn().artifact_id(&cid));
         rt.block_on(tx.conn().collector_start(interned_cid, &steps));
+        if let Some(metadata) = &metadata {
+            rt.block_on(tx.conn().record_compiler_metadata(interned_cid, metadata));
+        }
 
         rt.block_on(tx.commit()).unwrap();
         interned_cid
@@ -226,6 +346,13 @@ fn bench(
     let start = Instant::now();
     let mut skipped = false;
     for (nth_benchmark, benchmark) in benchmarks.iter().enumerate() {
+        if lease.map_or(false, Heartbeat::lost) {
+            eprintln!(
+                "stopping -- {} is now benchmarked by another collector",
+                cid
+            );
+            break;
+        }
         let is_fresh =
             rt.block_on(conn.collector_start_step(interned_cid, &benchmark.name.to_string()));
         if !is_fresh {
@@ -238,6 +365,17 @@ fn bench(
             tx.conn()
                 .record_benchmark(benchmark.name.0.as_str(), Some(benchmark.supports_stable())),
         );
+        // The rustc benchmark is built from the artifact's own sources.
+        if benchmark.name.0 != "rustc" {
+            match benchmark.source_hash() {
+                Ok(hash) => rt.block_on(tx.conn().record_benchmark_source(
+                    interned_cid,
+                    benchmark.name.0.as_str(),
+                    &hash,
+                )),
+                Err(e) => log::warn!("failed to hash {} sources: {:?}", benchmark.name, e),
+            }
+        }
         eprintln!(
             "{}",
             n_benchmarks_remaining(benchmarks.len() - nth_benchmark)
@@ -291,6 +429,113 @@ fn bench(
     errors
 }
 
+/// Installs a published toolchain (e.g. `1.47.0` or `beta`) with rustup and
+/// benchmarks it, recording the results under the toolchain's name.
+fn bench_published(
+    rt: &mut Runtime,
+    pool: database::Pool,
+    benchmark_dir: &Path,
+    toolchain: &str,
+    lease: Option<&Heartbeat>,
+) -> anyhow::Result<BenchmarkErrors> {
+    let status = Command::new("rustup")
+        .args(&["install", "--profile=minimal", &toolchain])
+        .status()
+        .context("rustup install")?;
+    if !status.success() {
+        anyhow::bail!("failed to install toolchain for {}", toolchain);
+    }
+
+    let run_kinds = if collector::version_supports_incremental(toolchain) {
+        RunKind::all()
+    } else {
+        RunKind::all_non_incr()
+    };
+    let build_kinds = if collector::version_supports_doc(toolchain) {
+        BuildKind::all()
+    } else {
+        let mut all = BuildKind::all();
+        let doc = all.iter().position(|bk| *bk == BuildKind::Doc).unwrap();
+        all.remove(doc);
+        all
+    };
+
+    let which = |tool| {
+        String::from_utf8(
+            Command::new("rustup")
+                .arg("which")
+                .arg("--toolchain")
+                .arg(&toolchain)
+                .arg(tool)
+                .output()
+                .context(format!("rustup which {}", tool))?
+                .stdout,
+        )
+        .context("utf8")
+    };
+    let rustc = which("rustc")?;
+    let rustdoc = which("rustdoc")?;
+    let cargo = which("cargo")?;
+
+    // Exclude benchmarks that don't work with a stable compiler.
+    let mut benchmarks = get_benchmarks(benchmark_dir, None, None)?;
+    benchmarks.retain(|b| b.supports_stable());
+
+    Ok(bench(
+        rt,
+        pool,
+        &ArtifactId::Artifact(toolchain.to_string()),
+        &build_kinds,
+        &run_kinds,
+        Compiler {
+            rustc: Path::new(rustc.trim()),
+            rustdoc: Some(Path::new(rustdoc.trim())),
+            cargo: Path::new(cargo.trim()),
+            is_nightly: false,
+            triple: "x86_64-unknown-linux-gnu",
+        },
+        &benchmarks,
+        Some(3),
+        /* self_profile */ false,
+        lease,
+    ))
+}
+
+/// Benchmarks a toolchain installed on this machine, e.g. a custom build of
+/// rustc queued on the site, recording the results under `name`.
+fn bench_local_toolchain(
+    rt: &mut Runtime,
+    pool: database::Pool,
+    benchmark_dir: &Path,
+    name: &str,
+    rustc: &str,
+    self_profile: bool,
+    lease: Option<&Heartbeat>,
+) -> anyhow::Result<BenchmarkErrors> {
+    let build_kinds = BuildKind::all();
+    let (rustc, rustdoc, cargo) = get_local_toolchain(&build_kinds, rustc, None, None)?;
+    let benchmarks = get_benchmarks(benchmark_dir, None, None)?;
+
+    Ok(bench(
+        rt,
+        pool,
+        &ArtifactId::Artifact(name.to_string()),
+        &build_kinds,
+        &RunKind::all(),
+        Compiler {
+            rustc: &rustc,
+            rustdoc: rustdoc.as_deref(),
+            cargo: &cargo,
+            triple: "x86_64-unknown-linux-gnu", // XXX: technically not necessarily true
+            is_nightly: true,
+        },
+        &benchmarks,
+        None,
+        self_profile,
+        lease,
+    ))
+}
+
 fn get_benchmarks(
     benchmark_dir: &Path,
     include: Option<&str>,
@@ -437,6 +682,24 @@ fn get_local_toolchain(
     Ok((rustc, rustdoc, cargo))
 }
 
+/// The name of this machine, which distinguishes it from the other collectors
+/// unless `--collector` is given.
+fn hostname() -> anyhow::Result<String> {
+    let output = Command::new("hostname")
+        .output()
+        .context("failed to run `hostname`")?;
+    if !output.status.success() {
+        bail!("`hostname` failed: {}", output.status);
+    }
+    let name =
+        String::from_utf8(output.stdout).context("failed to convert `hostname` output to utf8")?;
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("`hostname` printed nothing; pass `--collector`");
+    }
+    Ok(name.to_string())
+}
+
 fn main() {
     match main_result() {
         Ok(code) => process::exit(code),
@@ -491,6 +754,10 @@ fn main_result() -> anyhow::Result<i32> {
             (@arg SITE_URL: +required +takes_value "Site URL")
 
             // Options
+            (@arg COLLECTOR:    --collector +takes_value
+             "Name of this collector, which must be unique when\n\
+             several collectors benchmark for the same site\n\
+             (default: the hostname)")
             (@arg DB:           --db  +takes_value "Database output file")
             (@arg SELF_PROFILE: --("self-profile") "Collect self-profile data")
         )
@@ -596,6 +863,7 @@ fn main_result() -> anyhow::Result<i32> {
                 &benchmarks,
                 Some(1),
                 self_profile,
+                None,
             );
             res.fail_if_nonzero()?;
             Ok(0)
@@ -606,6 +874,11 @@ fn main_result() -> anyhow::Result<i32> {
             let site_url = sub_m.value_of("SITE_URL").unwrap();
 
             // Options
+            let collector_name = match sub_m.value_of("COLLECTOR") {
+                Some(name) => name.to_string(),
+                None => hostname()?,
+            };
+            let collector_name = collector_name.as_str();
             let db = sub_m.value_of("DB").unwrap_or(default_db);
             let self_profile = sub_m.is_present("SELF_PROFILE");
 
@@ -613,8 +886,54 @@ fn main_result() -> anyhow::Result<i32> {
             let client = reqwest::blocking::Client::new();
             let response: collector::api::next_commit::Response = client
                 .get(&format!("{}/perf/next_commit", site_url))
+                .query(&[("collector", collector_name)])
                 .send()?
                 .json()?;
+            if let Some(artifact) = response.artifact {
+                let heartbeat =
+                    Heartbeat::start(client.clone(), site_url, collector_name, &artifact.name);
+                let pool = database::Pool::open(db);
+                let res = match &artifact.local_rustc {
+                    Some(rustc) => bench_local_toolchain(
+                        &mut rt,
+                        pool,
+                        &benchmark_dir,
+                        &artifact.name,
+                        rustc,
+                        self_profile,
+                        Some(&heartbeat),
+                    ),
+                    None => bench_published(
+                        &mut rt,
+                        pool,
+                        &benchmark_dir,
+                        &artifact.name,
+                        Some(&heartbeat),
+                    ),
+                };
+                if heartbeat.lost() {
+                    bail!("lost the lease on {}", artifact.name);
+                }
+                if let Err(e) = &res {
+                    // Nothing was recorded for the artifact (e.g. its toolchain
+                    // couldn't be installed), so it would be handed out again
+                    // ahead of everything else. Take it out of the queue.
+                    let pool = database::Pool::open(db);
+                    let conn = rt.block_on(pool.connection());
+                    rt.block_on(conn.mark_artifact_failed(&artifact.name, &format!("{:?}", e)));
+                }
+
+                // Keep the lease until the site has picked up the results, so
+                // that no other collector is handed the artifact meanwhile.
+                client
+                    .post(&format!("{}/perf/onpush", site_url))
+                    .send()?
+                    .error_for_status()?;
+                heartbeat.finish();
+
+                res?.fail_if_nonzero()?;
+                return Ok(0);
+            }
             let next = if let Some(c) = response.commit {
                 c
             } else {
@@ -622,9 +941,9 @@ fn main_result() -> anyhow::Result<i32> {
                 // no missing commits
                 return Ok(0);
             };
-            let commit = get_commit_or_fake_it(&next.sha)?;
-
+            let heartbeat = Heartbeat::start(client.clone(), site_url, collector_name, &next.sha);
             let pool = database::Pool::open(db);
+            let commit = get_commit_or_fake_it(Some(&pool), &next.sha)?;
 
             let sysroot = Sysroot::install(commit.sha.to_string(), "x86_64-unknown-linux-gnu")
                 .with_context(|| format!("failed to install sysroot for {:?}", commit))?;
@@ -635,19 +954,36 @@ fn main_result() -> anyhow::Result<i32> {
                 next.exclude.as_deref(),
             )?;
 
+            let build_kinds = match &next.builds {
+                Some(builds) => kinds_from_arg("build", STRINGS_AND_BUILD_KINDS, builds)?,
+                None => BuildKind::all(),
+            };
+            let run_kinds = match &next.scenarios {
+                Some(scenarios) => kinds_from_arg("run", STRINGS_AND_RUN_KINDS, scenarios)?,
+                None => RunKind::all(),
+            };
+
             let res = bench(
                 &mut rt,
                 pool,
                 &ArtifactId::Commit(commit),
-                &BuildKind::all(),
-                &RunKind::all(),
+                &build_kinds,
+                &run_kinds,
                 Compiler::from_sysroot(&sysroot),
                 &benchmarks,
                 next.runs.map(|v| v as usize),
                 self_profile,
+                Some(&heartbeat),
             );
+            if heartbeat.lost() {
+                bail!("lost the lease on {}", next.sha);
+            }
 
-            client.post(&format!("{}/perf/onpush", site_url)).send()?;
+            client
+                .post(&format!("{}/perf/onpush", site_url))
+                .send()?
+                .error_for_status()?;
+            heartbeat.finish();
 
             res.fail_if_nonzero()?;
             Ok(0)
@@ -660,68 +996,9 @@ fn main_result() -> anyhow::Result<i32> {
             // Options
             let db = sub_m.value_of("DB").unwrap_or(default_db);
 
-            let status = Command::new("rustup")
-                .args(&["install", "--profile=minimal", &toolchain])
-                .status()
-                .context("rustup install")?;
-            if !status.success() {
-                anyhow::bail!("failed to install toolchain for {}", toolchain);
-            }
-
             let pool = database::Pool::open(db);
 
-            let run_kinds = if collector::version_supports_incremental(toolchain) {
-                RunKind::all()
-            } else {
-                RunKind::all_non_incr()
-            };
-            let build_kinds = if collector::version_supports_doc(toolchain) {
-                BuildKind::all()
-            } else {
-                let mut all = BuildKind::all();
-                let doc = all.iter().position(|bk| *bk == BuildKind::Doc).unwrap();
-                all.remove(doc);
-                all
-            };
-
-            let which = |tool| {
-                String::from_utf8(
-                    Command::new("rustup")
-                        .arg("which")
-                        .arg("--toolchain")
-                        .arg(&toolchain)
-                        .arg(tool)
-                        .output()
-                        .context(format!("rustup which {}", tool))?
-                        .stdout,
-                )
-                .context("utf8")
-            };
-            let rustc = which("rustc")?;
-            let rustdoc = which("rustdoc")?;
-            let cargo = which("cargo")?;
-
-            // Exclude benchmarks that don't work with a stable compiler.
-            let mut benchmarks = get_benchmarks(&benchmark_dir, None, None)?;
-            benchmarks.retain(|b| b.supports_stable());
-
-            let res = bench(
-                &mut rt,
-                pool,
-                &ArtifactId::Artifact(toolchain.to_string()),
-                &build_kinds,
-                &run_kinds,
-                Compiler {
-                    rustc: Path::new(rustc.trim()),
-                    rustdoc: Some(Path::new(rustdoc.trim())),
-                    cargo: Path::new(cargo.trim()),
-                    is_nightly: false,
-                    triple: "x86_64-unknown-linux-gnu",
-                },
-                &benchmarks,
-                Some(3),
-                /* self_profile */ false,
-            );
+            let res = bench_published(&mut rt, pool, &benchmark_dir, toolchain, None)?;
             res.fail_if_nonzero()?;
             Ok(0)
         }
@@ -786,7 +1063,7 @@ fn main_result() -> anyhow::Result<i32> {
                 .unwrap();
             let last_sha = String::from_utf8(last_sha.stdout).expect("utf8");
             let last_sha = last_sha.split_whitespace().next().expect(&last_sha);
-            let commit = get_commit_or_fake_it(&last_sha).expect("success");
+            let commit = get_commit_or_fake_it(None, &last_sha).expect("success");
             let mut sysroot = Sysroot::install(commit.sha.to_string(), "x86_64-unknown-linux-gnu")?;
             sysroot.preserve(); // don't delete it
 
@@ -806,17 +1083,36 @@ fn main_result() -> anyhow::Result<i32> {
     ret
 }
 
-pub fn get_commit_or_fake_it(sha: &str) -> anyhow::Result<Commit> {
+/// Looks `sha` up in the master commits cached in `pool`, and then in the
+/// list of recent master commits. Commits found in neither are assumed to be
+/// try commits. The list is only required if nothing is cached, so that
+/// commits can be benchmarked while it is unavailable.
+pub fn get_commit_or_fake_it(pool: Option<&database::Pool>, sha: &str) -> anyhow::Result<Commit> {
     let mut rt = tokio::runtime::Runtime::new().unwrap();
-    Ok(rt
-        .block_on(rustc_artifacts::master_commits())
-        .map_err(|e| anyhow::anyhow!("{:?}", e))
-        .context("getting master commit list")?
-        .into_iter()
-        .find(|c| c.sha == *sha)
-        .map(|c| Commit {
-            sha: c.sha.as_str().into(),
-            date: c.time.into(),
+    let cached = match pool {
+        Some(pool) => rt.block_on(async { pool.connection().await.master_commits().await }),
+        None => Vec::new(),
+    };
+    let time = match cached.iter().find(|c| c.sha == *sha) {
+        Some(c) => Some(c.time),
+        None => match rt.block_on(rustc_artifacts::master_commits()) {
+            Ok(commits) => commits.into_iter().find(|c| c.sha == *sha).map(|c| c.time),
+            Err(e) if !cached.is_empty() => {
+                log::warn!(
+                    "failed to get master commit list, using cached list: {:?}",
+                    e
+                );
+                None
+            }
+            Err(e) => {
+                return Err(anyhow::anyhow!("{:?}", e)).context("getting master commit list");
+            }
+        },
+    };
+    Ok(time
+        .map(|time| Commit {
+            sha: sha.into(),
+            date: time.into(),
         })
         .unwrap_or_else(|| {
             log::warn!("utilizing fake commit!");
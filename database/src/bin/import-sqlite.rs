@@ -38,6 +38,12 @@ async fn main() {
         let sqlite_aid = sqlite_conn.artifact_id(&aid).await;
         let postgres_aid = postgres_conn.artifact_id(&aid).await;
 
+        if let Some(metadata) = sqlite_conn.get_compiler_metadata(sqlite_aid).await {
+            postgres_conn
+                .record_compiler_metadata(postgres_aid, &metadata)
+                .await;
+        }
+
         for &(krate, profile, cache, stat) in sqlite_idx.all_pstat_series() {
             if benchmarks.insert(krate) {
                 postgres_conn.record_benchmark(krate.as_str(), None).await;
@@ -0,0 +1,53 @@
+use database::{ArtifactId, Pool};
+
+// Deletes the results of an artifact, or of some of its benchmarks, so that
+// the next collector running `bench_next` benchmarks them again.
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let db = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| panic!("needs database as 1st argument"));
+    let artifact = std::env::args()
+        .nth(2)
+        .unwrap_or_else(|| panic!("needs artifact (commit sha or name) as 2nd argument"));
+    // Starting after the database and artifact args, the rest are the
+    // benchmarks to invalidate. All of them are invalidated if none are given.
+    let benchmarks = std::env::args().skip(3).collect::<Vec<_>>();
+
+    let pool = Pool::open(&db);
+    let mut conn = pool.connection().await;
+    let artifact = conn
+        .artifact_by_name(&artifact)
+        .await
+        .unwrap_or_else(|| panic!("{} not found in database", artifact));
+
+    let mut tx = conn.transaction().await;
+    // Only queued artifacts are benchmarked again by `bench_next`.
+    if let ArtifactId::Artifact(name) = &artifact {
+        if !tx.conn().requeue_artifact(name).await {
+            panic!("{} was not queued, so it would not be re-run", name);
+        }
+    }
+    let aid = tx.conn().artifact_id(&artifact).await;
+    let steps = tx
+        .conn()
+        .invalidate_artifact(
+            aid,
+            if benchmarks.is_empty() {
+                None
+            } else {
+                Some(&benchmarks[..])
+            },
+        )
+        .await;
+    for benchmark in &benchmarks {
+        if !steps.contains(benchmark) {
+            panic!("{} was not benchmarked for {}", benchmark, artifact);
+        }
+    }
+    tx.commit().await.unwrap();
+
+    println!("Invalidated {} for {}", steps.join(", "), artifact);
+}
@@ -23,6 +23,102 @@ pub struct QueuedCommit {
     pub include: Option<String>,
     pub exclude: Option<String>,
     pub runs: Option<i32>,
+    /// Comma-separated build kinds to benchmark, e.g. `Check,Debug`.
+    pub builds: Option<String>,
+    /// Comma-separated scenarios (run kinds) to benchmark, e.g. `Full`.
+    pub scenarios: Option<String>,
+    /// Requested with `priority=high`, to be benchmarked before other try
+    /// builds.
+    pub high_priority: bool,
+}
+
+/// A PR merged as part of a rollup, benchmarked on its own through a
+/// generated perf-test PR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollupMember {
+    pub rollup_pr: u32,
+    /// The order in which the PR was merged into the rollup, starting at 0.
+    pub position: u32,
+    pub rolled_up_pr: u32,
+    /// The generated PR whose try build contains the rollup up to and
+    /// including `rolled_up_pr`.
+    pub perf_pr: u32,
+    /// The `(sha, parent_sha)` of the completed try build of `perf_pr`.
+    pub try_build: Option<(String, String)>,
+    /// The master commit the rollup was merged into, which the first member
+    /// is compared against. Not recorded for rollups unrolled before it was.
+    pub base_sha: Option<String>,
+}
+
+/// A named artifact (e.g. a release) waiting to be benchmarked by `bench_next`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedArtifact {
+    /// The name the results are recorded under; unless `local_rustc` is set,
+    /// also the rustup toolchain to install, e.g. `1.47.0` or `beta`.
+    pub name: String,
+    /// The path to a rustc on the collector machine to benchmark.
+    pub local_rustc: Option<String>,
+}
+
+/// A queued artifact which could not be benchmarked at all, e.g. because its
+/// toolchain could not be installed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedArtifact {
+    pub name: String,
+    pub error: String,
+}
+
+/// A commit merged into rust-lang/rust's master branch by bors, as cached in
+/// the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MasterCommit {
+    pub sha: String,
+    pub parent_sha: String,
+    /// The PR which was merged, if known.
+    pub pr: Option<u32>,
+    pub time: DateTime<Utc>,
+}
+
+/// A collector's claim on an artifact it is benchmarking, kept alive by
+/// heartbeats so that the artifact can be handed to another collector if it
+/// goes away.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectorLease {
+    pub collector: String,
+    /// The sha of the commit, or the name of the artifact, being benchmarked.
+    pub artifact: String,
+    pub acquired: DateTime<Utc>,
+    pub heartbeat: DateTime<Utc>,
+}
+
+/// A note on a range of artifacts, e.g. explaining a change in the results or
+/// marking a period as noisy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub id: u32,
+    /// The first and last artifact (commit sha or artifact name) covered,
+    /// which are the same for a single artifact.
+    pub start: String,
+    pub end: String,
+    /// Restrict the annotation to a benchmark, profile (e.g. `opt`) and
+    /// cache state (e.g. `incr-patched: println`); each applies to all if
+    /// not set.
+    pub benchmark: Option<String>,
+    pub profile: Option<String>,
+    pub cache: Option<String>,
+    pub author: String,
+    pub text: String,
+    pub created: Date,
+}
+
+impl Annotation {
+    /// Whether the annotation applies to the results of the given benchmark,
+    /// profile and cache state.
+    pub fn applies_to(&self, benchmark: &str, profile: &str, cache: &str) -> bool {
+        self.benchmark.as_deref().map_or(true, |b| b == benchmark)
+            && self.profile.as_deref().map_or(true, |p| p == profile)
+            && self.cache.as_deref().map_or(true, |c| c == cache)
+    }
 }
 
 #[derive(Debug, Hash, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -384,6 +480,16 @@ pub enum ArtifactId {
     Artifact(String),
 }
 
+impl ArtifactId {
+    /// The commit's sha, or the artifact's name.
+    pub fn name(&self) -> &str {
+        match self {
+            ArtifactId::Commit(c) => &c.sha,
+            ArtifactId::Artifact(id) => id,
+        }
+    }
+}
+
 impl fmt::Display for ArtifactId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -492,6 +598,39 @@ where
     {
         self.map.get(value).copied()
     }
+
+    fn watermark(&self) -> TableWatermark {
+        TableWatermark {
+            max_id: self.map.values().copied().max().unwrap_or(0),
+            rows: self.map.len() as u32,
+        }
+    }
+
+    fn extend(&mut self, other: Indexed<T>) {
+        self.map.extend(other.map);
+    }
+}
+
+/// Tracks how much of a single table has been loaded into an `Index`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TableWatermark {
+    /// The largest id seen, 0 if the table is empty.
+    pub max_id: u32,
+    pub rows: u32,
+}
+
+/// Tracks how much of the database has been loaded into an `Index`.
+///
+/// The ids are used to load only newer entries, whereas the row counts are
+/// used to detect removed entries or ids committed out of order, in which case
+/// the index needs to be fully reloaded.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct IndexWatermark {
+    /// Master, try and release artifacts.
+    pub artifacts: TableWatermark,
+    pub errors: TableWatermark,
+    pub pstats: TableWatermark,
+    pub queries: TableWatermark,
 }
 
 mod index_serde {
@@ -610,6 +749,53 @@ impl Index {
         conn.load_index().await
     }
 
+    /// Returns an index with the entries added to the database since this
+    /// index was loaded, or `None` if the database has not changed.
+    ///
+    /// Falls back to a full reload if the incrementally updated index does not
+    /// match the database, e.g., because entries were deleted.
+    pub async fn refresh(&self, conn: &mut dyn pool::Connection) -> Option<Index> {
+        let current = self.watermark();
+        let latest = conn.index_watermark().await;
+        if current == latest {
+            return None;
+        }
+
+        let mut index = self.clone();
+        index.extend(conn.load_index_since(&current).await);
+        if index.watermark() != latest {
+            log::info!(
+                "incremental index refresh diverged ({:?} != {:?}), reloading",
+                index.watermark(),
+                latest
+            );
+            return Some(Index::load(conn).await);
+        }
+        Some(index)
+    }
+
+    pub fn watermark(&self) -> IndexWatermark {
+        let commits = self.commits.watermark();
+        let artifacts = self.artifacts.watermark();
+        IndexWatermark {
+            artifacts: TableWatermark {
+                max_id: commits.max_id.max(artifacts.max_id),
+                rows: commits.rows + artifacts.rows,
+            },
+            errors: self.errors.watermark(),
+            pstats: self.pstats.watermark(),
+            queries: self.queries.watermark(),
+        }
+    }
+
+    fn extend(&mut self, other: Index) {
+        self.commits.extend(other.commits);
+        self.artifacts.extend(other.artifacts);
+        self.errors.extend(other.errors);
+        self.pstats.extend(other.pstats);
+        self.queries.extend(other.queries);
+    }
+
     pub fn lookup(&self, path: &DbLabel, cid: &ArtifactId) -> Option<(u32, ArtifactIdNumber)> {
         let cid = cid.lookup(self)?;
         let series = path.lookup(self)?;
@@ -694,6 +880,16 @@ impl Index {
     }
 }
 
+/// The compiler details reported by `rustc -vV` for an artifact.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompilerMetadata {
+    /// The first line of the output, e.g., `rustc 1.48.0 (7eac88abb 2020-11-16)`.
+    pub version: String,
+    pub commit_hash: Option<String>,
+    pub llvm_version: Option<String>,
+    pub host: String,
+}
+
 #[derive(Debug)]
 pub struct Step {
     pub name: String,
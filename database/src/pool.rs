@@ -1,5 +1,8 @@
-use crate::{ArtifactId, ArtifactIdNumber};
-use crate::{Cache, CollectionId, Index, Profile, QueryDatum, QueuedCommit, Step};
+use crate::{
+    Annotation, CollectorLease, FailedArtifact, MasterCommit, QueuedArtifact, RollupMember, Step,
+};
+use crate::{ArtifactId, ArtifactIdNumber, CompilerMetadata};
+use crate::{Cache, CollectionId, Index, IndexWatermark, Profile, QueryDatum, QueuedCommit};
 use chrono::{DateTime, Utc};
 use hashbrown::HashMap;
 use std::sync::{Arc, Mutex};
@@ -15,6 +18,11 @@ pub trait Connection: Send + Sync {
     async fn transaction(&mut self) -> Box<dyn Transaction + '_>;
 
     async fn load_index(&mut self) -> Index;
+    /// Loads the index entries with ids larger than those in the watermark.
+    async fn load_index_since(&mut self, since: &IndexWatermark) -> Index;
+    /// Returns the watermark of the index as it would currently be loaded
+    /// from the database.
+    async fn index_watermark(&self) -> IndexWatermark;
 
     async fn artifact_by_name(&self, artifact: &str) -> Option<ArtifactId>;
 
@@ -67,11 +75,30 @@ pub trait Connection: Send + Sync {
         krate: &str,
         value: Duration,
     );
+    /// Records the content hash of a benchmark's sources (including its patches
+    /// and `perf-config.json`) used when collecting data for an artifact.
+    async fn record_benchmark_source(&self, artifact: ArtifactIdNumber, krate: &str, hash: &str);
+    /// Records the compiler used for an artifact, replacing any previously
+    /// recorded metadata.
+    async fn record_compiler_metadata(
+        &self,
+        artifact: ArtifactIdNumber,
+        metadata: &CompilerMetadata,
+    );
 
     async fn get_bootstrap(
         &self,
         aids: &[ArtifactIdNumber],
     ) -> HashMap<String, Vec<Option<Duration>>>;
+    /// Returns the benchmark source hashes for each of the given artifacts, keyed
+    /// by benchmark.
+    async fn get_benchmark_sources(
+        &self,
+        aids: &[ArtifactIdNumber],
+    ) -> HashMap<String, Vec<Option<String>>>;
+    /// Returns the errors recorded for each of the given artifacts, keyed by
+    /// benchmark. Benchmarks which never failed at these artifacts are omitted.
+    async fn get_errors(&self, aids: &[ArtifactIdNumber]) -> HashMap<String, Vec<Option<String>>>;
     async fn get_pstats(
         &self,
         series: &[u32],
@@ -90,6 +117,7 @@ pub trait Connection: Send + Sync {
         cid: ArtifactIdNumber,
     ) -> Option<QueryDatum>;
     async fn get_error(&self, cid: ArtifactIdNumber) -> HashMap<String, Option<String>>;
+    async fn get_compiler_metadata(&self, aid: ArtifactIdNumber) -> Option<CompilerMetadata>;
 
     async fn queue_pr(
         &self,
@@ -97,14 +125,64 @@ pub trait Connection: Send + Sync {
         include: Option<&str>,
         exclude: Option<&str>,
         runs: Option<i32>,
+        builds: Option<&str>,
+        scenarios: Option<&str>,
+        high_priority: bool,
     );
+    /// Removes the PR's queued builds which have not been benchmarked yet.
+    /// Returns true if any were removed.
+    async fn cancel_pr(&self, pr: u32) -> bool;
     /// Returns true if this PR was queued waiting for a commit
     async fn pr_attach_commit(&self, pr: u32, sha: &str, parent_sha: &str) -> bool;
+    /// The try builds waiting to be benchmarked, high priority ones first and
+    /// otherwise in the order they were requested.
     async fn queued_commits(&self) -> Vec<QueuedCommit>;
     async fn mark_complete(&self, sha: &str) -> Option<QueuedCommit>;
 
+    async fn record_rollup_member(
+        &self,
+        rollup_pr: u32,
+        position: u32,
+        rolled_up_pr: u32,
+        perf_pr: u32,
+        base_sha: &str,
+    );
+    /// Returns the members of rollups whose results have not been reported
+    /// yet, ordered by rollup and position.
+    async fn unreported_rollup_members(&self) -> Vec<RollupMember>;
+    async fn mark_rollup_reported(&self, rollup_pr: u32);
+
+    /// Queues an artifact for benchmarking. Returns false if it is already
+    /// queued; an artifact which left the queue before is queued again. It is
+    /// up to the caller whether that makes sense for an artifact which already
+    /// has results.
+    async fn queue_artifact(&self, name: &str, local_rustc: Option<&str>) -> bool;
+    /// The artifacts waiting to be benchmarked, in the order they were queued.
+    async fn queued_artifacts(&self) -> Vec<QueuedArtifact>;
+    async fn mark_artifact_complete(&self, name: &str);
+    /// Takes an artifact which could not be benchmarked out of the queue,
+    /// recording why. Queueing it again clears the error.
+    async fn mark_artifact_failed(&self, name: &str, error: &str);
+    /// The artifacts which failed since they were last queued, most recently
+    /// queued first.
+    async fn failed_artifacts(&self) -> Vec<FailedArtifact>;
+    /// Queues a previously queued artifact again, with the same toolchain.
+    /// Returns false if it never went through the artifact queue.
+    async fn requeue_artifact(&self, name: &str) -> bool;
+
+    /// Caches master commits, e.g. as fetched from
+    /// `rustc_artifacts::master_commits`, replacing earlier entries for the
+    /// same sha. Cached commits older than all of `commits` are dropped, as
+    /// they fell out of the window of recent commits.
+    async fn record_master_commits(&self, commits: &[MasterCommit]);
+    /// The cached master commits, most recent first.
+    async fn master_commits(&self) -> Vec<MasterCommit>;
+
     // Collector status API
 
+    /// Records the steps of benchmarking `aid`, keeping the ones completed by
+    /// an earlier attempt. If an interrupted attempt or `invalidate_artifact`
+    /// left steps pending, only those are run and `steps` is ignored.
     async fn collector_start(&self, aid: ArtifactIdNumber, steps: &[String]);
 
     // Returns `true` if the step was started, i.e., it did not previously have
@@ -116,6 +194,28 @@ pub trait Connection: Send + Sync {
 
     async fn in_progress_steps(&self, aid: &ArtifactId) -> Vec<Step>;
 
+    /// Deletes the results of `benchmarks` (or of all benchmarks) for `aid`,
+    /// along with the aggregate indices computed from them, and marks their
+    /// steps as pending so that the artifact shows up as in progress and the
+    /// next collector re-runs just those steps. Benchmarks which have neither
+    /// results nor a step for `aid` are ignored. Returns the steps which were
+    /// reset.
+    async fn invalidate_artifact(
+        &self,
+        aid: ArtifactIdNumber,
+        benchmarks: Option<&[String]>,
+    ) -> Vec<String>;
+
+    /// Claims `artifact` for `collector`, releasing any other lease the
+    /// collector holds. Returns false if another collector holds the lease
+    /// and has sent a heartbeat within `expiry`.
+    async fn acquire_lease(&self, collector: &str, artifact: &str, expiry: Duration) -> bool;
+    /// Records a heartbeat. Returns false if the collector does not hold the
+    /// lease, e.g. because it expired and was handed to another collector.
+    async fn renew_lease(&self, collector: &str, artifact: &str) -> bool;
+    async fn release_lease(&self, collector: &str, artifact: &str);
+    async fn leases(&self) -> Vec<CollectorLease>;
+
     async fn last_end_time(&self) -> Option<DateTime<Utc>>;
 
     /// Returns the sha of the parent commit, if available.
@@ -128,10 +228,42 @@ pub trait Connection: Send + Sync {
     /// (Currently only works for try commits)
     async fn pr_of(&self, sha: &str) -> Option<u32>;
 
+    /// Returns whether the artifact has been checked for regressions against
+    /// its parent, and if so, whether it regressed.
+    async fn regression_check(&self, artifact: ArtifactIdNumber) -> Option<bool>;
+    /// Records the outcome of checking the artifact for regressions. Returns
+    /// false if a check was already recorded, in which case the outcome should
+    /// not be reported again.
+    async fn record_regression_check(
+        &self,
+        artifact: ArtifactIdNumber,
+        pr: Option<u32>,
+        regression: bool,
+    ) -> bool;
+
+    /// Records the value of the aggregate index `name` for the artifact,
+    /// relative to `baseline`, replacing any earlier value. `None` records
+    /// that the index can't be computed for the artifact. Invalidating
+    /// either artifact drops the value.
+    async fn record_aggregate_index(
+        &self,
+        name: &str,
+        artifact: ArtifactIdNumber,
+        baseline: ArtifactIdNumber,
+        value: Option<f64>,
+    );
+    /// The recorded values of the aggregate index `name`, by artifact.
+    async fn aggregate_index(&self, name: &str) -> HashMap<ArtifactIdNumber, Option<f64>>;
+
+    /// Stores the annotation under a new id, which is returned;
+    /// `annotation.id` is ignored.
+    async fn add_annotation(&self, annotation: &Annotation) -> u32;
+    /// Returns false if there is no annotation with the id.
+    async fn delete_annotation(&self, id: u32) -> bool;
+    /// All annotations, oldest first.
+    async fn annotations(&self) -> Vec<Annotation>;
+
     /// Returns the collection ids corresponding to the query. Usually just one.
-    ///
-    /// Currently only supported by postgres (sqlite does not store self-profile
-    /// results in the raw format).
     async fn list_self_profile(
         &self,
         aid: ArtifactId,
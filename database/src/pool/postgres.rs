@@ -1,7 +1,8 @@
 use crate::pool::{Connection, ConnectionManager, ManagedConnection, Transaction};
 use crate::{
-    ArtifactId, ArtifactIdNumber, Cache, CollectionId, Commit, Crate, Date, Index, Profile,
-    QueuedCommit,
+    Annotation, ArtifactId, ArtifactIdNumber, Cache, CollectionId, CollectorLease, Commit,
+    CompilerMetadata, Crate, Date, FailedArtifact, Index, IndexWatermark, MasterCommit, Profile,
+    QueuedArtifact, QueuedCommit, RollupMember, TableWatermark,
 };
 use anyhow::Context as _;
 use chrono::{DateTime, TimeZone, Utc};
@@ -149,8 +150,11 @@ static MIGRATIONS: &[&str] = &[
         requested timestamptz
     );
     "#,
-    // Prevent more than one queued entry per PR without a build
+    // Prevent more than one queued entry per PR without a build, keeping only
+    // one of any duplicates queued before.
     r#"
+    delete from pull_request_build a using pull_request_build b
+        where a.complete = false and b.complete = false and a.pr = b.pr and a.ctid > b.ctid;
     create unique index on pull_request_build (pr) where complete = false;
     "#,
     r#"
@@ -202,6 +206,95 @@ static MIGRATIONS: &[&str] = &[
     alter table rustc_compilation alter column aid set data type integer;
     alter table self_profile_query alter column aid set data type integer;
     "#,
+    r#"
+    create table benchmark_source(
+        aid integer not null references artifact(id) on delete cascade on update cascade,
+        crate text not null references benchmark(name) on delete cascade on update cascade,
+        hash text not null,
+        PRIMARY KEY(aid, crate)
+    );
+    "#,
+    r#"
+    create table compiler_metadata(
+        aid integer primary key not null references artifact(id) on delete cascade on update cascade,
+        version text not null,
+        commit_hash text,
+        llvm_version text,
+        host text not null
+    );
+    "#,
+    r#"
+    create table regression_check(
+        aid integer primary key not null references artifact(id) on delete cascade on update cascade,
+        pr integer,
+        regression boolean not null
+    );
+    "#,
+    r#"
+    create table rollup_member(
+        rollup_pr integer not null,
+        position integer not null,
+        rolled_up_pr integer not null,
+        perf_pr integer not null,
+        reported boolean not null default false,
+        PRIMARY KEY(rollup_pr, position)
+    );
+    "#,
+    r#"alter table pull_request_build add column high_priority boolean not null default false;"#,
+    r#"alter table pull_request_build add column builds text;"#,
+    r#"alter table pull_request_build add column scenarios text;"#,
+    r#"
+    create table collector_lease(
+        artifact text primary key not null,
+        collector text not null,
+        acquired timestamptz not null,
+        heartbeat timestamptz not null
+    );
+    "#,
+    r#"
+    create table artifact_queue(
+        name text primary key not null,
+        local_rustc text,
+        requested timestamptz not null,
+        complete boolean not null default false
+    );
+    "#,
+    r#"
+    create table master_commit(
+        sha text primary key not null,
+        parent_sha text not null,
+        pr integer,
+        time timestamptz not null
+    );
+    "#,
+    r#"
+    create table aggregate_index(
+        name text not null,
+        aid integer not null references artifact(id) on delete cascade on update cascade,
+        baseline integer not null references artifact(id) on delete cascade on update cascade,
+        value double precision,
+        PRIMARY KEY(name, aid)
+    );
+    "#,
+    r#"
+    create table annotation(
+        id serial primary key,
+        start_artifact text not null,
+        end_artifact text not null,
+        crate text,
+        profile text,
+        cache text,
+        author text not null,
+        text text not null,
+        created timestamptz not null
+    );
+    "#,
+    r#"
+    alter table artifact_queue add column error text;
+    "#,
+    r#"
+    alter table rollup_member add column base_sha text;
+    "#,
 ];
 
 #[async_trait::async_trait]
@@ -473,12 +566,16 @@ where
     }
 
     async fn load_index(&mut self) -> Index {
+        self.load_index_since(&IndexWatermark::default()).await
+    }
+    async fn load_index_since(&mut self, since: &IndexWatermark) -> Index {
         Index {
             commits: self
                 .conn()
                 .query(
-                    "select id, name, date from artifact where type = 'master' or type = 'try'",
-                    &[],
+                    "select id, name, date from artifact
+                    where (type = 'master' or type = 'try') and id > $1",
+                    &[&(since.artifacts.max_id as i32)],
                 )
                 .await
                 .unwrap()
@@ -501,7 +598,10 @@ where
                 .collect(),
             artifacts: self
                 .conn()
-                .query("select id, name from artifact where type = 'release'", &[])
+                .query(
+                    "select id, name from artifact where type = 'release' and id > $1",
+                    &[&(since.artifacts.max_id as i32)],
+                )
                 .await
                 .unwrap()
                 .into_iter()
@@ -514,7 +614,10 @@ where
                 .collect(),
             errors: self
                 .conn()
-                .query("select id, crate from error_series", &[])
+                .query(
+                    "select id, crate from error_series where id > $1",
+                    &[&(since.errors.max_id as i32)],
+                )
                 .await
                 .unwrap()
                 .into_iter()
@@ -528,8 +631,8 @@ where
             pstats: self
                 .conn()
                 .query(
-                    "select id, crate, profile, cache, statistic from pstat_series;",
-                    &[],
+                    "select id, crate, profile, cache, statistic from pstat_series where id > $1;",
+                    &[&(since.pstats.max_id as i32)],
                 )
                 .await
                 .unwrap()
@@ -555,8 +658,8 @@ where
             queries: self
                 .conn()
                 .query(
-                    "select id, crate, profile, cache, query from self_profile_query_series;",
-                    &[],
+                    "select id, crate, profile, cache, query from self_profile_query_series where id > $1;",
+                    &[&(since.queries.max_id as i32)],
                 )
                 .await
                 .unwrap()
@@ -581,6 +684,36 @@ where
                 .collect(),
         }
     }
+    async fn index_watermark(&self) -> IndexWatermark {
+        let row = self
+            .conn()
+            .query_one(
+                "select
+                    (select max(id) from artifact
+                        where type = 'master' or type = 'try' or type = 'release'),
+                    (select count(*) from artifact
+                        where type = 'master' or type = 'try' or type = 'release'),
+                    (select max(id) from error_series),
+                    (select count(*) from error_series),
+                    (select max(id) from pstat_series),
+                    (select count(*) from pstat_series),
+                    (select max(id) from self_profile_query_series),
+                    (select count(*) from self_profile_query_series)",
+                &[],
+            )
+            .await
+            .unwrap();
+        let table = |idx: usize| TableWatermark {
+            max_id: row.get::<_, Option<i32>>(idx).unwrap_or(0) as u32,
+            rows: row.get::<_, i64>(idx + 1) as u32,
+        };
+        IndexWatermark {
+            artifacts: table(0),
+            errors: table(2),
+            pstats: table(4),
+            queries: table(6),
+        }
+    }
     async fn get_pstats(
         &self,
         series: &[u32],
@@ -668,21 +801,58 @@ where
             .map(|row| (row.get(0), row.get(1)))
             .collect()
     }
+    async fn get_compiler_metadata(&self, aid: ArtifactIdNumber) -> Option<CompilerMetadata> {
+        self.conn()
+            .query_opt(
+                "select version, commit_hash, llvm_version, host from compiler_metadata
+                where aid = $1",
+                &[&(aid.0 as i32)],
+            )
+            .await
+            .unwrap()
+            .map(|row| CompilerMetadata {
+                version: row.get(0),
+                commit_hash: row.get(1),
+                llvm_version: row.get(2),
+                host: row.get(3),
+            })
+    }
     async fn queue_pr(
         &self,
         pr: u32,
         include: Option<&str>,
         exclude: Option<&str>,
         runs: Option<i32>,
+        builds: Option<&str>,
+        scenarios: Option<&str>,
+        high_priority: bool,
     ) {
         self.conn()
             .execute(
-                "insert into pull_request_build (pr, complete, requested, include, exclude, runs) VALUES ($1, false, CURRENT_TIMESTAMP, $2, $3, $4) ON CONFLICT DO NOTHING",
-                &[&(pr as i32), &include, &exclude, &runs],
+                "insert into pull_request_build (pr, complete, requested, include, exclude, runs, builds, scenarios, high_priority) VALUES ($1, false, CURRENT_TIMESTAMP, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING",
+                &[
+                    &(pr as i32),
+                    &include,
+                    &exclude,
+                    &runs,
+                    &builds,
+                    &scenarios,
+                    &high_priority,
+                ],
             )
             .await
             .unwrap();
     }
+    async fn cancel_pr(&self, pr: u32) -> bool {
+        self.conn()
+            .execute(
+                "delete from pull_request_build where pr = $1 and complete = false",
+                &[&(pr as i32)],
+            )
+            .await
+            .unwrap()
+            > 0
+    }
     async fn pr_attach_commit(&self, pr: u32, sha: &str, parent_sha: &str) -> bool {
         self.conn()
             .execute(
@@ -698,9 +868,10 @@ where
         let rows = self
             .conn()
             .query(
-                "select pr, bors_sha, parent_sha, include, exclude, runs from pull_request_build
+                "select pr, bors_sha, parent_sha, include, exclude, runs, builds, scenarios, high_priority
+                from pull_request_build
                 where complete is false and bors_sha is not null
-                order by requested asc",
+                order by high_priority desc, requested asc",
                 &[],
             )
             .await
@@ -713,6 +884,9 @@ where
                 include: row.get(3),
                 exclude: row.get(4),
                 runs: row.get(5),
+                builds: row.get(6),
+                scenarios: row.get(7),
+                high_priority: row.get(8),
             })
             .collect()
     }
@@ -722,7 +896,7 @@ where
             .query_opt(
                 "update pull_request_build SET complete = true
                 where bors_sha = $1
-                returning pr, bors_sha, parent_sha, include, exclude, runs",
+                returning pr, bors_sha, parent_sha, include, exclude, runs, builds, scenarios, high_priority",
                 &[&sha],
             )
             .await
@@ -734,8 +908,199 @@ where
             include: row.get(3),
             exclude: row.get(4),
             runs: row.get(5),
+            builds: row.get(6),
+            scenarios: row.get(7),
+            high_priority: row.get(8),
         })
     }
+    async fn record_rollup_member(
+        &self,
+        rollup_pr: u32,
+        position: u32,
+        rolled_up_pr: u32,
+        perf_pr: u32,
+        base_sha: &str,
+    ) {
+        self.conn()
+            .execute(
+                "insert into rollup_member (rollup_pr, position, rolled_up_pr, perf_pr, base_sha)
+                VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &(rollup_pr as i32),
+                    &(position as i32),
+                    &(rolled_up_pr as i32),
+                    &(perf_pr as i32),
+                    &base_sha,
+                ],
+            )
+            .await
+            .unwrap();
+    }
+    async fn unreported_rollup_members(&self) -> Vec<RollupMember> {
+        let rows = self
+            .conn()
+            .query(
+                "select rollup_pr, position, rolled_up_pr, perf_pr, bors_sha, parent_sha,
+                    base_sha
+                from rollup_member
+                left join pull_request_build on pr = perf_pr and complete
+                where not reported
+                order by rollup_pr, position",
+                &[],
+            )
+            .await
+            .unwrap();
+        let mut members = rows
+            .into_iter()
+            .map(|row| {
+                let bors_sha: Option<String> = row.get(4);
+                let parent_sha: Option<String> = row.get(5);
+                RollupMember {
+                    rollup_pr: row.get::<_, i32>(0) as u32,
+                    position: row.get::<_, i32>(1) as u32,
+                    rolled_up_pr: row.get::<_, i32>(2) as u32,
+                    perf_pr: row.get::<_, i32>(3) as u32,
+                    try_build: bors_sha.zip(parent_sha),
+                    base_sha: row.get(6),
+                }
+            })
+            .collect::<Vec<_>>();
+        // A perf PR may have several completed try builds; keep the first.
+        members.dedup_by_key(|m| (m.rollup_pr, m.position));
+        members
+    }
+    async fn mark_rollup_reported(&self, rollup_pr: u32) {
+        self.conn()
+            .execute(
+                "update rollup_member set reported = true where rollup_pr = $1",
+                &[&(rollup_pr as i32)],
+            )
+            .await
+            .unwrap();
+    }
+    async fn queue_artifact(&self, name: &str, local_rustc: Option<&str>) -> bool {
+        self.conn()
+            .execute(
+                "insert into artifact_queue (name, local_rustc, requested, complete)
+                VALUES ($1, $2, CURRENT_TIMESTAMP, false)
+                ON CONFLICT (name) do update set
+                    local_rustc = excluded.local_rustc,
+                    requested = excluded.requested,
+                    complete = false,
+                    error = null
+                where artifact_queue.complete",
+                &[&name, &local_rustc],
+            )
+            .await
+            .unwrap()
+            == 1
+    }
+    async fn queued_artifacts(&self) -> Vec<QueuedArtifact> {
+        self.conn()
+            .query(
+                "select name, local_rustc from artifact_queue
+                where not complete order by requested asc",
+                &[],
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| QueuedArtifact {
+                name: row.get(0),
+                local_rustc: row.get(1),
+            })
+            .collect()
+    }
+    async fn mark_artifact_complete(&self, name: &str) {
+        self.conn()
+            .execute(
+                "update artifact_queue set complete = true where name = $1",
+                &[&name],
+            )
+            .await
+            .unwrap();
+    }
+    async fn mark_artifact_failed(&self, name: &str, error: &str) {
+        self.conn()
+            .execute(
+                "update artifact_queue set complete = true, error = $2 where name = $1",
+                &[&name, &error],
+            )
+            .await
+            .unwrap();
+    }
+    async fn failed_artifacts(&self) -> Vec<FailedArtifact> {
+        self.conn()
+            .query(
+                "select name, error from artifact_queue
+                where error is not null order by requested desc",
+                &[],
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| FailedArtifact {
+                name: row.get(0),
+                error: row.get(1),
+            })
+            .collect()
+    }
+    async fn record_master_commits(&self, commits: &[MasterCommit]) {
+        if let Some(oldest) = commits.iter().map(|c| c.time).min() {
+            self.conn()
+                .execute("delete from master_commit where time < $1", &[&oldest])
+                .await
+                .unwrap();
+        }
+        for commit in commits {
+            self.conn()
+                .execute(
+                    "insert into master_commit (sha, parent_sha, pr, time)
+                    VALUES ($1, $2, $3, $4)
+                    ON CONFLICT (sha) do update set
+                        parent_sha = excluded.parent_sha,
+                        pr = excluded.pr,
+                        time = excluded.time",
+                    &[
+                        &commit.sha,
+                        &commit.parent_sha,
+                        &commit.pr.map(|pr| pr as i32),
+                        &commit.time,
+                    ],
+                )
+                .await
+                .unwrap();
+        }
+    }
+    async fn master_commits(&self) -> Vec<MasterCommit> {
+        self.conn()
+            .query(
+                "select sha, parent_sha, pr, time from master_commit order by time desc",
+                &[],
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| MasterCommit {
+                sha: row.get(0),
+                parent_sha: row.get(1),
+                pr: row.get::<_, Option<i32>>(2).map(|pr| pr as u32),
+                time: row.get(3),
+            })
+            .collect()
+    }
+    async fn requeue_artifact(&self, name: &str) -> bool {
+        self.conn()
+            .execute(
+                "update artifact_queue set complete = false, requested = CURRENT_TIMESTAMP,
+                    error = null
+                where name = $1",
+                &[&name],
+            )
+            .await
+            .unwrap()
+            == 1
+    }
     async fn collection_id(&self, version: &str) -> CollectionId {
         CollectionId(
             self.conn()
@@ -816,6 +1181,43 @@ where
             .unwrap();
     }
 
+    async fn record_benchmark_source(&self, artifact: ArtifactIdNumber, krate: &str, hash: &str) {
+        self.conn()
+            .execute(
+                "insert into benchmark_source (aid, crate, hash) VALUES ($1, $2, $3)
+                ON CONFLICT (aid, crate) DO UPDATE SET hash = EXCLUDED.hash",
+                &[&(artifact.0 as i32), &krate, &hash],
+            )
+            .await
+            .unwrap();
+    }
+
+    async fn record_compiler_metadata(
+        &self,
+        artifact: ArtifactIdNumber,
+        metadata: &CompilerMetadata,
+    ) {
+        self.conn()
+            .execute(
+                "insert into compiler_metadata (aid, version, commit_hash, llvm_version, host)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (aid) DO UPDATE SET
+                    version = EXCLUDED.version,
+                    commit_hash = EXCLUDED.commit_hash,
+                    llvm_version = EXCLUDED.llvm_version,
+                    host = EXCLUDED.host",
+                &[
+                    &(artifact.0 as i32),
+                    &metadata.version,
+                    &metadata.commit_hash,
+                    &metadata.llvm_version,
+                    &metadata.host,
+                ],
+            )
+            .await
+            .unwrap();
+    }
+
     async fn artifact_id(&self, artifact: &ArtifactId) -> ArtifactIdNumber {
         let (name, date, ty) = match artifact {
             ArtifactId::Commit(commit) => (
@@ -977,14 +1379,21 @@ where
     }
 
     async fn collector_start(&self, aid: ArtifactIdNumber, steps: &[String]) {
-        // Clean up -- we'll re-insert any missing things in the loop below.
-        self.conn()
+        // Reset any leftover unterminated steps, which are resumed as is.
+        // Other artifacts may be in progress on other collectors, so only
+        // touch this one.
+        let resumed = self
+            .conn()
             .execute(
-                "delete from collector_progress where start_time is null or end_time is null;",
-                &[],
+                "update collector_progress set start_time = null \
+                where aid = $1 and end_time is null;",
+                &[&(aid.0 as i32)],
             )
             .await
             .unwrap();
+        if resumed > 0 {
+            return;
+        }
 
         for step in steps {
             self.conn()
@@ -1029,7 +1438,7 @@ where
         let rows = self
             .conn()
             .query(
-                "select distinct aid from collector_progress where end_time is null order by aid limit 1",
+                "select distinct aid from collector_progress where end_time is null order by aid",
                 &[],
             )
             .await
@@ -1094,6 +1503,164 @@ where
             })
             .collect()
     }
+    async fn invalidate_artifact(
+        &self,
+        aid: ArtifactIdNumber,
+        benchmarks: Option<&[String]>,
+    ) -> Vec<String> {
+        let aid = aid.0 as i32;
+        let known = self
+            .conn()
+            .query(
+                "select step from collector_progress where aid = $1
+                union select crate from pstat_series
+                    where id in (select series from pstat where aid = $1)
+                union select crate from error_series
+                    where id in (select series from error where aid = $1)
+                order by 1",
+                &[&aid],
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect::<Vec<String>>();
+        // A step for a benchmark which never ran for the artifact would never
+        // be started, leaving the artifact in progress forever.
+        let steps = match benchmarks {
+            Some(benchmarks) => known
+                .into_iter()
+                .filter(|step| benchmarks.contains(step))
+                .collect(),
+            None => known,
+        };
+        for step in &steps {
+            for (table, series) in &[
+                ("pstat", "pstat_series"),
+                ("self_profile_query", "self_profile_query_series"),
+                ("error", "error_series"),
+            ] {
+                self.conn()
+                    .execute(
+                        format!(
+                            "delete from {} where aid = $1 and series in
+                            (select id from {} where crate = $2)",
+                            table, series
+                        )
+                        .as_str(),
+                        &[&aid, step],
+                    )
+                    .await
+                    .unwrap();
+            }
+            for table in &["raw_self_profile", "benchmark_source"] {
+                self.conn()
+                    .execute(
+                        format!("delete from {} where aid = $1 and crate = $2", table).as_str(),
+                        &[&aid, step],
+                    )
+                    .await
+                    .unwrap();
+            }
+            self.conn()
+                .execute(
+                    "delete from collector_progress where aid = $1 and step = $2",
+                    &[&aid, step],
+                )
+                .await
+                .unwrap();
+            self.conn()
+                .execute(
+                    "insert into collector_progress(aid, step) VALUES ($1, $2)",
+                    &[&aid, step],
+                )
+                .await
+                .unwrap();
+        }
+        if steps.iter().any(|step| step == "rustc") {
+            self.conn()
+                .execute("delete from rustc_compilation where aid = $1", &[&aid])
+                .await
+                .unwrap();
+        }
+        if benchmarks.is_none() {
+            self.conn()
+                .execute(
+                    "delete from artifact_collection_duration where aid = $1",
+                    &[&aid],
+                )
+                .await
+                .unwrap();
+        }
+        self.conn()
+            .execute(
+                "delete from aggregate_index where aid = $1 or baseline = $1",
+                &[&aid],
+            )
+            .await
+            .unwrap();
+        steps
+    }
+    async fn acquire_lease(&self, collector: &str, artifact: &str, expiry: Duration) -> bool {
+        // A single statement, so that the collector's previous lease is only
+        // released together with the new one being acquired.
+        self.conn()
+            .execute(
+                "with released as (
+                    delete from collector_lease where collector = $2 and artifact != $1
+                )
+                insert into collector_lease (artifact, collector, acquired, heartbeat)
+                VALUES ($1, $2, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+                ON CONFLICT (artifact) do update set
+                    collector = excluded.collector,
+                    acquired = excluded.acquired,
+                    heartbeat = excluded.heartbeat
+                where collector_lease.collector = excluded.collector
+                    or collector_lease.heartbeat < CURRENT_TIMESTAMP - make_interval(secs => $3)",
+                &[&artifact, &collector, &expiry.as_secs_f64()],
+            )
+            .await
+            .unwrap()
+            == 1
+    }
+    async fn renew_lease(&self, collector: &str, artifact: &str) -> bool {
+        self.conn()
+            .execute(
+                "update collector_lease set heartbeat = CURRENT_TIMESTAMP
+                where collector = $1 and artifact = $2",
+                &[&collector, &artifact],
+            )
+            .await
+            .unwrap()
+            == 1
+    }
+    async fn release_lease(&self, collector: &str, artifact: &str) {
+        self.conn()
+            .execute(
+                "delete from collector_lease where collector = $1 and artifact = $2",
+                &[&collector, &artifact],
+            )
+            .await
+            .unwrap();
+    }
+    async fn leases(&self) -> Vec<CollectorLease> {
+        self.conn()
+            .query(
+                "select collector, artifact, acquired, heartbeat
+                from collector_lease order by collector",
+                &[],
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| CollectorLease {
+                collector: row.get(0),
+                artifact: row.get(1),
+                acquired: row.get(2),
+                heartbeat: row.get(3),
+            })
+            .collect()
+    }
     async fn last_end_time(&self) -> Option<DateTime<Utc>> {
         self.conn()
             .query_opt(
@@ -1127,6 +1694,115 @@ where
             .unwrap()
             .map(|r| r.get::<_, i32>(0) as u32)
     }
+    async fn regression_check(&self, artifact: ArtifactIdNumber) -> Option<bool> {
+        self.conn()
+            .query_opt(
+                "select regression from regression_check where aid = $1",
+                &[&(artifact.0 as i32)],
+            )
+            .await
+            .unwrap()
+            .map(|r| r.get(0))
+    }
+    async fn record_regression_check(
+        &self,
+        artifact: ArtifactIdNumber,
+        pr: Option<u32>,
+        regression: bool,
+    ) -> bool {
+        self.conn()
+            .execute(
+                "insert into regression_check (aid, pr, regression) VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING",
+                &[&(artifact.0 as i32), &pr.map(|pr| pr as i32), &regression],
+            )
+            .await
+            .unwrap()
+            > 0
+    }
+    async fn record_aggregate_index(
+        &self,
+        name: &str,
+        artifact: ArtifactIdNumber,
+        baseline: ArtifactIdNumber,
+        value: Option<f64>,
+    ) {
+        self.conn()
+            .execute(
+                "insert into aggregate_index (name, aid, baseline, value) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (name, aid) do update set
+                    baseline = excluded.baseline,
+                    value = excluded.value",
+                &[&name, &(artifact.0 as i32), &(baseline.0 as i32), &value],
+            )
+            .await
+            .unwrap();
+    }
+    async fn aggregate_index(&self, name: &str) -> HashMap<ArtifactIdNumber, Option<f64>> {
+        self.conn()
+            .query(
+                "select aid, value from aggregate_index where name = $1",
+                &[&name],
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| (ArtifactIdNumber(row.get::<_, i32>(0) as u32), row.get(1)))
+            .collect()
+    }
+    async fn add_annotation(&self, annotation: &Annotation) -> u32 {
+        let row = self
+            .conn()
+            .query_one(
+                "insert into annotation
+                (start_artifact, end_artifact, crate, profile, cache, author, text, created)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                returning id",
+                &[
+                    &annotation.start,
+                    &annotation.end,
+                    &annotation.benchmark,
+                    &annotation.profile,
+                    &annotation.cache,
+                    &annotation.author,
+                    &annotation.text,
+                    &annotation.created.0,
+                ],
+            )
+            .await
+            .unwrap();
+        row.get::<_, i32>(0) as u32
+    }
+    async fn delete_annotation(&self, id: u32) -> bool {
+        self.conn()
+            .execute("delete from annotation where id = $1", &[&(id as i32)])
+            .await
+            .unwrap()
+            == 1
+    }
+    async fn annotations(&self) -> Vec<Annotation> {
+        self.conn()
+            .query(
+                "select id, start_artifact, end_artifact, crate, profile, cache, author, text, created
+                from annotation order by id",
+                &[],
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| Annotation {
+                id: row.get::<_, i32>(0) as u32,
+                start: row.get(1),
+                end: row.get(2),
+                benchmark: row.get(3),
+                profile: row.get(4),
+                cache: row.get(5),
+                author: row.get(6),
+                text: row.get(7),
+                created: Date(row.get(8)),
+            })
+            .collect()
+    }
     async fn record_raw_self_profile(
         &self,
         collection: CollectionId,
@@ -1209,6 +1885,67 @@ where
         result
     }
 
+    async fn get_benchmark_sources(
+        &self,
+        aids: &[ArtifactIdNumber],
+    ) -> HashMap<String, Vec<Option<String>>> {
+        let mut result = HashMap::new();
+        let aid_to_idx = aids
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(idx, v)| (v, idx))
+            .collect::<HashMap<ArtifactIdNumber, usize>>();
+        let rows = self
+            .conn()
+            .query(
+                "select aid, crate, hash from benchmark_source where aid = any($1)",
+                &[&aids.iter().map(|v| v.0 as i32).collect::<Vec<_>>()],
+            )
+            .await
+            .unwrap();
+
+        for row in rows {
+            let aid = ArtifactIdNumber(row.get::<_, i32>(0) as u32);
+            let v = result
+                .entry(row.get::<_, String>(1))
+                .or_insert_with(|| vec![None; aids.len()]);
+            v[aid_to_idx[&aid]] = Some(row.get::<_, String>(2));
+        }
+
+        result
+    }
+
+    async fn get_errors(&self, aids: &[ArtifactIdNumber]) -> HashMap<String, Vec<Option<String>>> {
+        let mut result = HashMap::new();
+        let aid_to_idx = aids
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(idx, v)| (v, idx))
+            .collect::<HashMap<ArtifactIdNumber, usize>>();
+        let rows = self
+            .conn()
+            .query(
+                "select aid, crate, error from error
+                join error_series on error.series = error_series.id
+                where aid = any($1)",
+                &[&aids.iter().map(|v| v.0 as i32).collect::<Vec<_>>()],
+            )
+            .await
+            .unwrap();
+
+        for row in rows {
+            let aid = ArtifactIdNumber(row.get::<_, i32>(0) as u32);
+            let v = result
+                .entry(row.get::<_, String>(1))
+                .or_insert_with(|| vec![None; aids.len()]);
+            v[aid_to_idx[&aid]] = Some(row.get::<_, Option<String>>(2).unwrap_or_default());
+        }
+
+        result
+    }
+
     async fn artifact_by_name(&self, artifact: &str) -> Option<ArtifactId> {
         let row = self
             .conn()
@@ -1,6 +1,9 @@
 use crate::pool::{Connection, ConnectionManager, ManagedConnection, Transaction};
-use crate::{ArtifactId, CollectionId, Commit, Crate, Date, Profile};
-use crate::{ArtifactIdNumber, Index, QueryDatum, QueuedCommit};
+use crate::{
+    Annotation, CollectorLease, FailedArtifact, MasterCommit, QueuedArtifact, RollupMember,
+};
+use crate::{ArtifactId, CollectionId, Commit, CompilerMetadata, Crate, Date, Profile};
+use crate::{ArtifactIdNumber, Index, IndexWatermark, QueryDatum, QueuedCommit, TableWatermark};
 use chrono::{DateTime, TimeZone, Utc};
 use hashbrown::HashMap;
 use rusqlite::params;
@@ -162,6 +165,112 @@ static MIGRATIONS: &[&str] = &[
         PRIMARY KEY(aid, cid, crate)
     );
     "#,
+    r#"
+    create table raw_self_profile(
+        aid integer references artifact(id) on delete cascade on update cascade,
+        cid integer references collection(id) on delete cascade on update cascade,
+        crate text not null references benchmark(name) on delete cascade on update cascade,
+        profile text not null,
+        cache text not null,
+        PRIMARY KEY(aid, cid, crate, profile, cache)
+    );
+    "#,
+    // Prevent more than one queued entry per PR without a build, keeping the
+    // first of any duplicates queued before.
+    r#"
+    delete from pull_request_builds where complete = 0 and rowid not in
+        (select min(rowid) from pull_request_builds where complete = 0 group by pr);
+    create unique index queued_pull_request_builds on pull_request_builds (pr) where complete = 0;
+    "#,
+    r#"
+    create table benchmark_source(
+        aid integer not null references artifact(id) on delete cascade on update cascade,
+        crate text not null references benchmark(name) on delete cascade on update cascade,
+        hash text not null,
+        PRIMARY KEY(aid, crate)
+    );
+    "#,
+    r#"
+    create table compiler_metadata(
+        aid integer primary key not null references artifact(id) on delete cascade on update cascade,
+        version text not null,
+        commit_hash text,
+        llvm_version text,
+        host text not null
+    );
+    "#,
+    r#"
+    create table regression_check(
+        aid integer primary key not null references artifact(id) on delete cascade on update cascade,
+        pr integer,
+        regression boolean not null
+    );
+    "#,
+    r#"
+    create table rollup_member(
+        rollup_pr integer not null,
+        position integer not null,
+        rolled_up_pr integer not null,
+        perf_pr integer not null,
+        reported boolean not null default 0,
+        PRIMARY KEY(rollup_pr, position)
+    );
+    "#,
+    r#"alter table pull_request_builds add column high_priority boolean not null default 0;"#,
+    r#"alter table pull_request_builds add column builds text;"#,
+    r#"alter table pull_request_builds add column scenarios text;"#,
+    r#"
+    create table collector_lease(
+        artifact text primary key not null,
+        collector text not null,
+        acquired integer not null,
+        heartbeat integer not null
+    );
+    "#,
+    r#"
+    create table artifact_queue(
+        name text primary key not null,
+        local_rustc text,
+        requested integer not null,
+        complete boolean not null default 0
+    );
+    "#,
+    r#"
+    create table master_commit(
+        sha text primary key not null,
+        parent_sha text not null,
+        pr integer,
+        time integer not null
+    );
+    "#,
+    r#"
+    create table aggregate_index(
+        name text not null,
+        aid integer not null references artifact(id) on delete cascade on update cascade,
+        baseline integer not null references artifact(id) on delete cascade on update cascade,
+        value real,
+        PRIMARY KEY(name, aid)
+    );
+    "#,
+    r#"
+    create table annotation(
+        id integer primary key autoincrement,
+        start_artifact text not null,
+        end_artifact text not null,
+        crate text,
+        profile text,
+        cache text,
+        author text not null,
+        text text not null,
+        created integer not null
+    );
+    "#,
+    r#"
+    alter table artifact_queue add column error text;
+    "#,
+    r#"
+    alter table rollup_member add column base_sha text;
+    "#,
 ];
 
 #[async_trait::async_trait]
@@ -249,11 +358,18 @@ impl Connection for SqliteConnection {
     }
 
     async fn load_index(&mut self) -> Index {
+        self.load_index_since(&IndexWatermark::default()).await
+    }
+
+    async fn load_index_since(&mut self, since: &IndexWatermark) -> Index {
         let commits = self
             .raw()
-            .prepare("select id, name, date from artifact where type = 'master' or type = 'try'")
+            .prepare(
+                "select id, name, date from artifact
+                where (type = 'master' or type = 'try') and id > ?",
+            )
             .unwrap()
-            .query_map(params![], |row| {
+            .query_map(params![since.artifacts.max_id], |row| {
                 Ok((
                     row.get::<_, i32>(0)? as u32,
                     Commit {
@@ -273,9 +389,9 @@ impl Connection for SqliteConnection {
             .collect();
         let artifacts = self
             .raw()
-            .prepare("select id, name from artifact where type = 'release'")
+            .prepare("select id, name from artifact where type = 'release' and id > ?")
             .unwrap()
-            .query_map(params![], |row| {
+            .query_map(params![since.artifacts.max_id], |row| {
                 Ok((
                     row.get::<_, i32>(0)? as u32,
                     row.get::<_, String>(1)?.into_boxed_str(),
@@ -286,9 +402,11 @@ impl Connection for SqliteConnection {
             .collect();
         let queries = self
             .raw()
-            .prepare("select id, crate, profile, cache, query from self_profile_query_series;")
+            .prepare(
+                "select id, crate, profile, cache, query from self_profile_query_series where id > ?;",
+            )
             .unwrap()
-            .query_map(params![], |row| {
+            .query_map(params![since.queries.max_id], |row| {
                 Ok((
                     row.get::<_, i32>(0)? as u32,
                     (
@@ -310,9 +428,9 @@ impl Connection for SqliteConnection {
             .collect();
         let errors = self
             .raw()
-            .prepare("select id, crate from error_series")
+            .prepare("select id, crate from error_series where id > ?")
             .unwrap()
-            .query_map(params![], |row| {
+            .query_map(params![since.errors.max_id], |row| {
                 Ok((
                     row.get::<_, i32>(0)? as u32,
                     row.get::<_, String>(1)?.as_str().into(),
@@ -327,9 +445,11 @@ impl Connection for SqliteConnection {
             errors,
             pstats: self
                 .raw()
-                .prepare("select id, crate, profile, cache, statistic from pstat_series;")
+                .prepare(
+                    "select id, crate, profile, cache, statistic from pstat_series where id > ?;",
+                )
                 .unwrap()
-                .query_map(params![], |row| {
+                .query_map(params![since.pstats.max_id], |row| {
                     Ok((
                         row.get::<_, i32>(0)? as u32,
                         (
@@ -353,6 +473,28 @@ impl Connection for SqliteConnection {
         }
     }
 
+    async fn index_watermark(&self) -> IndexWatermark {
+        let conn = self.raw_ref();
+        let table = |query: &str| {
+            conn.query_row(query, params![], |row| {
+                Ok(TableWatermark {
+                    max_id: row.get::<_, Option<i32>>(0)?.unwrap_or(0) as u32,
+                    rows: row.get::<_, i32>(1)? as u32,
+                })
+            })
+            .unwrap()
+        };
+        IndexWatermark {
+            artifacts: table(
+                "select max(id), count(*) from artifact
+                where type = 'master' or type = 'try' or type = 'release'",
+            ),
+            errors: table("select max(id), count(*) from error_series"),
+            pstats: table("select max(id), count(*) from pstat_series"),
+            queries: table("select max(id), count(*) from self_profile_query_series"),
+        }
+    }
+
     async fn get_pstats(
         &self,
         series: &[u32],
@@ -461,21 +603,59 @@ impl Connection for SqliteConnection {
             .collect::<Result<_, _>>()
             .unwrap()
     }
+    async fn get_compiler_metadata(&self, aid: ArtifactIdNumber) -> Option<CompilerMetadata> {
+        self.raw_ref()
+            .prepare_cached(
+                "select version, commit_hash, llvm_version, host from compiler_metadata
+                where aid = ?",
+            )
+            .unwrap()
+            .query_row(params![&aid.0], |row| {
+                Ok(CompilerMetadata {
+                    version: row.get(0)?,
+                    commit_hash: row.get(1)?,
+                    llvm_version: row.get(2)?,
+                    host: row.get(3)?,
+                })
+            })
+            .optional()
+            .unwrap()
+    }
     async fn queue_pr(
         &self,
         pr: u32,
         include: Option<&str>,
         exclude: Option<&str>,
         runs: Option<i32>,
+        builds: Option<&str>,
+        scenarios: Option<&str>,
+        high_priority: bool,
     ) {
         self.raw_ref()
             .prepare_cached(
-                "insert into pull_request_builds (pr, complete, requested, include, exclude, runs) VALUES (?, 0, strftime('%s','now'), ?, ?, ?)",
+                "insert or ignore into pull_request_builds (pr, complete, requested, include, exclude, runs, builds, scenarios, high_priority) VALUES (?, 0, strftime('%s','now'), ?, ?, ?, ?, ?, ?)",
             )
             .unwrap()
-            .execute(params![pr, include, exclude, &runs])
+            .execute(params![
+                pr,
+                include,
+                exclude,
+                &runs,
+                builds,
+                scenarios,
+                &high_priority
+            ])
             .unwrap();
     }
+    async fn cancel_pr(&self, pr: u32) -> bool {
+        self.raw_ref()
+            .execute(
+                "delete from pull_request_builds where pr = ? and complete = 0",
+                params![pr],
+            )
+            .unwrap()
+            > 0
+    }
     async fn pr_attach_commit(&self, pr: u32, sha: &str, parent_sha: &str) -> bool {
         self.raw_ref()
             .prepare_cached(
@@ -490,9 +670,10 @@ impl Connection for SqliteConnection {
     async fn queued_commits(&self) -> Vec<QueuedCommit> {
         self.raw_ref()
             .prepare_cached(
-                "select pr, bors_sha, parent_sha, include, exclude, runs from pull_request_builds
+                "select pr, bors_sha, parent_sha, include, exclude, runs, builds, scenarios, high_priority
+                from pull_request_builds
                 where complete is false and bors_sha is not null
-                order by requested asc",
+                order by high_priority desc, requested asc",
             )
             .unwrap()
             .query(params![])
@@ -505,6 +686,9 @@ impl Connection for SqliteConnection {
                     include: row.get(3).unwrap(),
                     exclude: row.get(4).unwrap(),
                     runs: row.get(5).unwrap(),
+                    builds: row.get(6).unwrap(),
+                    scenarios: row.get(7).unwrap(),
+                    high_priority: row.get(8).unwrap(),
                 })
             })
             .collect::<Result<Vec<_>, _>>()
@@ -514,7 +698,7 @@ impl Connection for SqliteConnection {
         let count = self
             .raw_ref()
             .execute(
-                "update pull_request_builds SET complete = 1 where sha = ? and complete = 0",
+                "update pull_request_builds SET complete = 1 where bors_sha = ? and complete = 0",
                 params![sha],
             )
             .unwrap();
@@ -524,8 +708,9 @@ impl Connection for SqliteConnection {
         assert_eq!(count, 1, "sha is unique column");
         self.raw_ref()
             .query_row(
-                "select pr, sha, parent_sha, include, exclude, runs from pull_request_builds
-            where sha = ?",
+                "select pr, bors_sha, parent_sha, include, exclude, runs, builds, scenarios, high_priority
+                from pull_request_builds
+            where bors_sha = ?",
                 params![sha],
                 |row| {
                     Ok(QueuedCommit {
@@ -535,17 +720,199 @@ impl Connection for SqliteConnection {
                         include: row.get(3).unwrap(),
                         exclude: row.get(4).unwrap(),
                         runs: row.get(5).unwrap(),
+                        builds: row.get(6).unwrap(),
+                        scenarios: row.get(7).unwrap(),
+                        high_priority: row.get(8).unwrap(),
                     })
                 },
             )
             .optional()
             .unwrap()
     }
+    async fn record_rollup_member(
+        &self,
+        rollup_pr: u32,
+        position: u32,
+        rolled_up_pr: u32,
+        perf_pr: u32,
+        base_sha: &str,
+    ) {
+        self.raw_ref()
+            .execute(
+                "insert into rollup_member (rollup_pr, position, rolled_up_pr, perf_pr, base_sha)
+                VALUES (?, ?, ?, ?, ?)",
+                params![&rollup_pr, &position, &rolled_up_pr, &perf_pr, base_sha],
+            )
+            .unwrap();
+    }
+    async fn unreported_rollup_members(&self) -> Vec<RollupMember> {
+        let mut members = self
+            .raw_ref()
+            .prepare_cached(
+                "select rollup_pr, position, rolled_up_pr, perf_pr, bors_sha, parent_sha,
+                    base_sha
+                from rollup_member
+                left join pull_request_builds on pr = perf_pr and complete = 1
+                where reported = 0
+                order by rollup_pr, position",
+            )
+            .unwrap()
+            .query(params![])
+            .unwrap()
+            .mapped(|row| {
+                let bors_sha: Option<String> = row.get(4).unwrap();
+                let parent_sha: Option<String> = row.get(5).unwrap();
+                Ok(RollupMember {
+                    rollup_pr: row.get(0).unwrap(),
+                    position: row.get(1).unwrap(),
+                    rolled_up_pr: row.get(2).unwrap(),
+                    perf_pr: row.get(3).unwrap(),
+                    try_build: bors_sha.zip(parent_sha),
+                    base_sha: row.get(6).unwrap(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        // A perf PR may have several completed try builds; keep the first.
+        members.dedup_by_key(|m| (m.rollup_pr, m.position));
+        members
+    }
+    async fn mark_rollup_reported(&self, rollup_pr: u32) {
+        self.raw_ref()
+            .execute(
+                "update rollup_member set reported = 1 where rollup_pr = ?",
+                params![&rollup_pr],
+            )
+            .unwrap();
+    }
+    async fn queue_artifact(&self, name: &str, local_rustc: Option<&str>) -> bool {
+        self.raw_ref()
+            .execute(
+                "insert into artifact_queue (name, local_rustc, requested, complete)
+                VALUES (?, ?, strftime('%s','now'), 0)
+                ON CONFLICT (name) do update set
+                    local_rustc = excluded.local_rustc,
+                    requested = excluded.requested,
+                    complete = 0,
+                    error = null
+                where artifact_queue.complete",
+                params![name, local_rustc],
+            )
+            .unwrap()
+            == 1
+    }
+    async fn queued_artifacts(&self) -> Vec<QueuedArtifact> {
+        self.raw_ref()
+            .prepare_cached(
+                "select name, local_rustc from artifact_queue
+                where not complete order by requested asc",
+            )
+            .unwrap()
+            .query_map(params![], |row| {
+                Ok(QueuedArtifact {
+                    name: row.get(0)?,
+                    local_rustc: row.get(1)?,
+                })
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+    async fn mark_artifact_complete(&self, name: &str) {
+        self.raw_ref()
+            .execute(
+                "update artifact_queue set complete = 1 where name = ?",
+                params![name],
+            )
+            .unwrap();
+    }
+    async fn mark_artifact_failed(&self, name: &str, error: &str) {
+        self.raw_ref()
+            .execute(
+                "update artifact_queue set complete = 1, error = ? where name = ?",
+                params![error, name],
+            )
+            .unwrap();
+    }
+    async fn failed_artifacts(&self) -> Vec<FailedArtifact> {
+        self.raw_ref()
+            .prepare_cached(
+                "select name, error from artifact_queue
+                where error is not null order by requested desc",
+            )
+            .unwrap()
+            .query_map(params![], |row| {
+                Ok(FailedArtifact {
+                    name: row.get(0)?,
+                    error: row.get(1)?,
+                })
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+    async fn record_master_commits(&self, commits: &[MasterCommit]) {
+        let conn = self.raw_ref();
+        if let Some(oldest) = commits.iter().map(|c| c.time).min() {
+            conn.execute(
+                "delete from master_commit where time < ?",
+                params![oldest.timestamp()],
+            )
+            .unwrap();
+        }
+        let mut insert = conn
+            .prepare_cached(
+                "insert into master_commit (sha, parent_sha, pr, time) VALUES (?, ?, ?, ?)
+                ON CONFLICT (sha) do update set
+                    parent_sha = excluded.parent_sha,
+                    pr = excluded.pr,
+                    time = excluded.time",
+            )
+            .unwrap();
+        for commit in commits {
+            insert
+                .execute(params![
+                    commit.sha,
+                    commit.parent_sha,
+                    commit.pr,
+                    commit.time.timestamp()
+                ])
+                .unwrap();
+        }
+    }
+    async fn master_commits(&self) -> Vec<MasterCommit> {
+        self.raw_ref()
+            .prepare_cached(
+                "select sha, parent_sha, pr, time from master_commit order by time desc",
+            )
+            .unwrap()
+            .query_map(params![], |row| {
+                Ok(MasterCommit {
+                    sha: row.get(0)?,
+                    parent_sha: row.get(1)?,
+                    pr: row.get(2)?,
+                    time: Utc.timestamp(row.get(3)?, 0),
+                })
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+    async fn requeue_artifact(&self, name: &str) -> bool {
+        self.raw_ref()
+            .execute(
+                "update artifact_queue set complete = 0, requested = strftime('%s','now'), \
+                error = null where name = ?",
+                params![name],
+            )
+            .unwrap()
+            == 1
+    }
     async fn collection_id(&self, version: &str) -> CollectionId {
         let raw = self.raw_ref();
         raw.execute(
             "insert into collection (perf_commit) values (?)",
-            params![version],
+            params![version.trim()],
         )
         .unwrap();
         CollectionId(
@@ -610,6 +977,41 @@ impl Connection for SqliteConnection {
             .unwrap();
     }
 
+    async fn record_benchmark_source(&self, artifact: ArtifactIdNumber, krate: &str, hash: &str) {
+        self.raw_ref()
+            .execute(
+                "insert into benchmark_source (aid, crate, hash) VALUES (?, ?, ?)
+                ON CONFLICT (aid, crate) do update set hash = excluded.hash",
+                params![&artifact.0, &krate, &hash],
+            )
+            .unwrap();
+    }
+
+    async fn record_compiler_metadata(
+        &self,
+        artifact: ArtifactIdNumber,
+        metadata: &CompilerMetadata,
+    ) {
+        self.raw_ref()
+            .execute(
+                "insert into compiler_metadata (aid, version, commit_hash, llvm_version, host)
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT (aid) do update set
+                    version = excluded.version,
+                    commit_hash = excluded.commit_hash,
+                    llvm_version = excluded.llvm_version,
+                    host = excluded.host",
+                params![
+                    &artifact.0,
+                    &metadata.version,
+                    &metadata.commit_hash,
+                    &metadata.llvm_version,
+                    &metadata.host,
+                ],
+            )
+            .unwrap();
+    }
+
     async fn artifact_id(&self, artifact: &crate::ArtifactId) -> ArtifactIdNumber {
         let (name, date, ty) = match artifact {
             crate::ArtifactId::Commit(commit) => (
@@ -733,12 +1135,21 @@ impl Connection for SqliteConnection {
         }
     }
     async fn collector_start(&self, aid: ArtifactIdNumber, steps: &[String]) {
-        // Clean out any leftover unterminated steps.
-        self.raw_ref()
-            .execute_batch("delete from collector_progress where start is null or end is null;")
+        // Reset any leftover unterminated steps, which are resumed as is.
+        // Other artifacts may be in progress on other collectors, so only
+        // touch this one.
+        let resumed = self
+            .raw_ref()
+            .execute(
+                "update collector_progress set start = null where aid = ? and end is null;",
+                params![&aid.0],
+            )
             .unwrap();
+        if resumed > 0 {
+            return;
+        }
 
-        // Populate unstarted and unfinished steps into collector_progress.
+        // Populate unstarted steps into collector_progress.
         for step in steps {
             self.raw_ref()
                 .execute(
@@ -777,7 +1188,7 @@ impl Connection for SqliteConnection {
         let mut aids = conn
             .prepare(
                 "select distinct aid from collector_progress \
-                where end is null order by aid limit 1",
+                where end is null order by aid",
             )
             .unwrap();
 
@@ -825,14 +1236,16 @@ impl Connection for SqliteConnection {
                 "
                 select
                     step,
-                    end_time is not null,
+                    end is not null,
                     coalesce(end, strftime('%s', 'now')) - start,
                     (select end - start
                         from collector_progress as cp
                             where
-                                cp.step = collector_progress.step
+                                cp.aid != collector_progress.aid
+                                and cp.step = collector_progress.step
                                 and cp.start is not null
                                 and cp.end is not null
+                            order by start desc
                             limit 1
                     )
                 from collector_progress where aid = ? order by step
@@ -843,8 +1256,161 @@ impl Connection for SqliteConnection {
                 Ok(crate::Step {
                     name: row.get(0)?,
                     is_done: row.get(1)?,
-                    duration: Duration::from_secs(row.get::<_, i64>(2)? as u64),
-                    expected: Duration::from_secs(row.get::<_, i64>(3)? as u64),
+                    duration: Duration::from_secs(
+                        row.get::<_, Option<i64>>(2)?.unwrap_or(0) as u64,
+                    ),
+                    expected: Duration::from_secs(
+                        row.get::<_, Option<i64>>(3)?.unwrap_or(0) as u64,
+                    ),
+                })
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+    async fn invalidate_artifact(
+        &self,
+        aid: ArtifactIdNumber,
+        benchmarks: Option<&[String]>,
+    ) -> Vec<String> {
+        let conn = self.raw_ref();
+        let known = conn
+            .prepare(
+                "select step from collector_progress where aid = ?1
+                union select crate from pstat_series
+                    where id in (select series from pstat where aid = ?1)
+                union select crate from error_series
+                    where id in (select series from error where aid = ?1)
+                order by 1",
+            )
+            .unwrap()
+            .query_map(params![&aid.0], |row| row.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect::<Vec<String>>();
+        // A step for a benchmark which never ran for the artifact would never
+        // be started, leaving the artifact in progress forever.
+        let steps = match benchmarks {
+            Some(benchmarks) => known
+                .into_iter()
+                .filter(|step| benchmarks.contains(step))
+                .collect(),
+            None => known,
+        };
+        for step in &steps {
+            for (table, series) in &[
+                ("pstat", "pstat_series"),
+                ("self_profile_query", "self_profile_query_series"),
+                ("error", "error_series"),
+            ] {
+                conn.execute(
+                    &format!(
+                        "delete from {} where aid = ? and series in \
+                        (select id from {} where crate = ?)",
+                        table, series
+                    ),
+                    params![&aid.0, step],
+                )
+                .unwrap();
+            }
+            for table in &["raw_self_profile", "benchmark_source"] {
+                conn.execute(
+                    &format!("delete from {} where aid = ? and crate = ?", table),
+                    params![&aid.0, step],
+                )
+                .unwrap();
+            }
+            conn.execute(
+                "delete from collector_progress where aid = ? and step = ?",
+                params![&aid.0, step],
+            )
+            .unwrap();
+            conn.execute(
+                "insert into collector_progress(aid, step) VALUES (?, ?)",
+                params![&aid.0, step],
+            )
+            .unwrap();
+        }
+        if steps.iter().any(|step| step == "rustc") {
+            conn.execute(
+                "delete from rustc_compilation where aid = ?",
+                params![&aid.0],
+            )
+            .unwrap();
+        }
+        if benchmarks.is_none() {
+            conn.execute(
+                "delete from artifact_collection_duration where aid = ?",
+                params![&aid.0],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "delete from aggregate_index where aid = ? or baseline = ?",
+            params![&aid.0, &aid.0],
+        )
+        .unwrap();
+        steps
+    }
+    async fn acquire_lease(&self, collector: &str, artifact: &str, expiry: Duration) -> bool {
+        let mut conn = self.raw_ref();
+        // A savepoint rather than a transaction, as this may run inside one.
+        // The delete takes the write lock, so no other connection can lease
+        // the artifact before the insert.
+        let tx = conn.savepoint().unwrap();
+        tx.execute(
+            "delete from collector_lease where collector = ? and artifact != ?",
+            params![collector, artifact],
+        )
+        .unwrap();
+        let acquired = tx
+            .execute(
+                "insert into collector_lease (artifact, collector, acquired, heartbeat)
+                VALUES (?, ?, strftime('%s','now'), strftime('%s','now'))
+                ON CONFLICT (artifact) do update set
+                    collector = excluded.collector,
+                    acquired = excluded.acquired,
+                    heartbeat = excluded.heartbeat
+                where collector_lease.collector = excluded.collector
+                    or collector_lease.heartbeat < strftime('%s','now') - ?",
+                params![artifact, collector, &(expiry.as_secs() as i64)],
+            )
+            .unwrap()
+            == 1;
+        tx.commit().unwrap();
+        acquired
+    }
+    async fn renew_lease(&self, collector: &str, artifact: &str) -> bool {
+        self.raw_ref()
+            .execute(
+                "update collector_lease set heartbeat = strftime('%s','now')
+                where collector = ? and artifact = ?",
+                params![collector, artifact],
+            )
+            .unwrap()
+            == 1
+    }
+    async fn release_lease(&self, collector: &str, artifact: &str) {
+        self.raw_ref()
+            .execute(
+                "delete from collector_lease where collector = ? and artifact = ?",
+                params![collector, artifact],
+            )
+            .unwrap();
+    }
+    async fn leases(&self) -> Vec<CollectorLease> {
+        self.raw_ref()
+            .prepare_cached(
+                "select collector, artifact, acquired, heartbeat
+                from collector_lease order by collector",
+            )
+            .unwrap()
+            .query_map(params![], |row| {
+                Ok(CollectorLease {
+                    collector: row.get(0)?,
+                    artifact: row.get(1)?,
+                    acquired: Utc.timestamp(row.get(2)?, 0),
+                    heartbeat: Utc.timestamp(row.get(3)?, 0),
                 })
             })
             .unwrap()
@@ -854,7 +1420,7 @@ impl Connection for SqliteConnection {
     async fn last_end_time(&self) -> Option<DateTime<Utc>> {
         self.raw_ref()
             .query_row(
-                "select date_recorded + duration \
+                "select date_recorded \
                 from artifact_collection_duration \
                 order by date_recorded desc \
                 limit 1;",
@@ -887,28 +1453,156 @@ impl Connection for SqliteConnection {
             .optional()
             .unwrap()
     }
+    async fn regression_check(&self, artifact: ArtifactIdNumber) -> Option<bool> {
+        self.raw_ref()
+            .query_row(
+                "select regression from regression_check where aid = ?",
+                params![&artifact.0],
+                |row| Ok(row.get(0).unwrap()),
+            )
+            .optional()
+            .unwrap()
+    }
+    async fn record_regression_check(
+        &self,
+        artifact: ArtifactIdNumber,
+        pr: Option<u32>,
+        regression: bool,
+    ) -> bool {
+        self.raw_ref()
+            .execute(
+                "insert or ignore into regression_check (aid, pr, regression) VALUES (?, ?, ?)",
+                params![&artifact.0, &pr, &regression],
+            )
+            .unwrap()
+            > 0
+    }
+    async fn record_aggregate_index(
+        &self,
+        name: &str,
+        artifact: ArtifactIdNumber,
+        baseline: ArtifactIdNumber,
+        value: Option<f64>,
+    ) {
+        self.raw_ref()
+            .execute(
+                "insert into aggregate_index (name, aid, baseline, value) VALUES (?, ?, ?, ?)
+                ON CONFLICT (name, aid) do update set
+                    baseline = excluded.baseline,
+                    value = excluded.value",
+                params![name, &artifact.0, &baseline.0, &value],
+            )
+            .unwrap();
+    }
+    async fn aggregate_index(&self, name: &str) -> HashMap<ArtifactIdNumber, Option<f64>> {
+        self.raw_ref()
+            .prepare_cached("select aid, value from aggregate_index where name = ?")
+            .unwrap()
+            .query_map(params![name], |row| {
+                Ok((ArtifactIdNumber(row.get(0)?), row.get(1)?))
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+    async fn add_annotation(&self, annotation: &Annotation) -> u32 {
+        let conn = self.raw_ref();
+        conn.execute(
+            "insert into annotation
+            (start_artifact, end_artifact, crate, profile, cache, author, text, created)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                annotation.start,
+                annotation.end,
+                annotation.benchmark,
+                annotation.profile,
+                annotation.cache,
+                annotation.author,
+                annotation.text,
+                annotation.created.0.timestamp(),
+            ],
+        )
+        .unwrap();
+        conn.last_insert_rowid() as u32
+    }
+    async fn delete_annotation(&self, id: u32) -> bool {
+        self.raw_ref()
+            .execute("delete from annotation where id = ?", params![&id])
+            .unwrap()
+            == 1
+    }
+    async fn annotations(&self) -> Vec<Annotation> {
+        self.raw_ref()
+            .prepare_cached(
+                "select id, start_artifact, end_artifact, crate, profile, cache, author, text, created
+                from annotation order by id",
+            )
+            .unwrap()
+            .query_map(params![], |row| {
+                Ok(Annotation {
+                    id: row.get(0)?,
+                    start: row.get(1)?,
+                    end: row.get(2)?,
+                    benchmark: row.get(3)?,
+                    profile: row.get(4)?,
+                    cache: row.get(5)?,
+                    author: row.get(6)?,
+                    text: row.get(7)?,
+                    created: Date(Utc.timestamp(row.get(8)?, 0)),
+                })
+            })
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
     async fn record_raw_self_profile(
         &self,
-        _collection: CollectionId,
-        _artifact: ArtifactIdNumber,
-        _krate: &str,
-        _profile: Profile,
-        _cache: crate::Cache,
+        collection: CollectionId,
+        artifact: ArtifactIdNumber,
+        krate: &str,
+        profile: Profile,
+        cache: crate::Cache,
     ) {
-        // FIXME: this is left for the future, if we ever need to support it. It
-        // shouldn't be too hard, but we may also want to just intern the raw
-        // self profile files into sqlite database or something like that, not
-        // yet clear.
-        unimplemented!("recording raw self profile files is not implemented for sqlite")
+        let profile = profile.to_string();
+        let cache = cache.to_string();
+        self.raw_ref()
+            .execute(
+                "insert into raw_self_profile (aid, cid, crate, profile, cache) VALUES (?, ?, ?, ?, ?)",
+                params![&artifact.0, &collection.0, &krate, &profile, &cache],
+            )
+            .unwrap();
     }
     async fn list_self_profile(
         &self,
-        _aid: ArtifactId,
-        _crate_: &str,
-        _profile: &str,
-        _cache: &str,
+        aid: ArtifactId,
+        crate_: &str,
+        profile: &str,
+        cache: &str,
     ) -> Vec<(ArtifactIdNumber, i32)> {
-        Vec::new()
+        self.raw_ref()
+            .prepare(
+                "select aid, cid from raw_self_profile where
+                    crate = ?
+                    and profile = ?
+                    and cache = ?
+                    and aid = (select id from artifact where name = ?);",
+            )
+            .unwrap()
+            .query_map(
+                params![
+                    &crate_,
+                    &profile,
+                    &cache,
+                    &match aid {
+                        ArtifactId::Commit(c) => c.sha,
+                        ArtifactId::Artifact(a) => a,
+                    }
+                ],
+                |r| Ok((ArtifactIdNumber(r.get::<_, i32>(0)? as u32), r.get(1)?)),
+            )
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
     }
 
     async fn get_bootstrap(
@@ -940,6 +1634,66 @@ impl Connection for SqliteConnection {
             }
         }
 
+        for v in results.values_mut() {
+            v.resize_with(aids.len(), || None);
+        }
+
+        results
+    }
+
+    async fn get_benchmark_sources(
+        &self,
+        aids: &[ArtifactIdNumber],
+    ) -> HashMap<String, Vec<Option<String>>> {
+        let mut results = HashMap::new();
+
+        let conn = self.raw_ref();
+        let mut query = conn
+            .prepare_cached("select crate, hash from benchmark_source where aid = ?")
+            .unwrap();
+        for (idx, aid) in aids.iter().enumerate() {
+            let rows = query
+                .query_map(params![&aid.0], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })
+                .unwrap()
+                .map(|r| r.unwrap());
+            for (krate, hash) in rows {
+                results
+                    .entry(krate)
+                    .or_insert_with(|| vec![None; aids.len()])[idx] = Some(hash);
+            }
+        }
+
+        results
+    }
+
+    async fn get_errors(&self, aids: &[ArtifactIdNumber]) -> HashMap<String, Vec<Option<String>>> {
+        let mut results = HashMap::new();
+
+        let conn = self.raw_ref();
+        let mut query = conn
+            .prepare_cached(
+                "select crate, error from error
+                join error_series on error.series = error_series.id
+                where aid = ?",
+            )
+            .unwrap();
+        for (idx, aid) in aids.iter().enumerate() {
+            let rows = query
+                .query_map(params![&aid.0], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+                })
+                .unwrap()
+                .map(|r| r.unwrap());
+            for (krate, error) in rows {
+                results
+                    .entry(krate)
+                    .or_insert_with(|| vec![None; aids.len()])[idx] =
+                    Some(error.unwrap_or_default());
+            }
+        }
+
         results
     }
 
@@ -0,0 +1,884 @@
+//! Conformance tests for the `Connection` implementations.
+//!
+//! Every check runs against a fresh in-memory sqlite database. If
+//! `DATABASE_TEST_POSTGRES_URL` is set (e.g. to
+//! `postgres://postgres@localhost/rustc_perf_test`), the same checks also run
+//! against that postgres database. Each check runs inside a transaction which
+//! is rolled back afterwards, so no data is left behind; on a fresh postgres
+//! database run with `--test-threads=1` so that migrations are applied once.
+
+use chrono::{TimeZone, Utc};
+use database::pool::Connection;
+use database::{
+    Annotation, ArtifactId, Cache, Commit, CompilerMetadata, Crate, Date, DbLabel, FailedArtifact,
+    Index, Lookup, MasterCommit, Pool, ProcessStatistic, Profile, QueryDatum, QueryLabel,
+    QueuedArtifact, QueuedCommit, RollupMember,
+};
+use std::time::Duration;
+
+const POSTGRES_URL_VAR: &str = "DATABASE_TEST_POSTGRES_URL";
+
+fn pools() -> Vec<Pool> {
+    let mut pools = vec![Pool::open(":memory:")];
+    if let Ok(url) = std::env::var(POSTGRES_URL_VAR) {
+        assert!(
+            url.starts_with("postgres"),
+            "{} must be a postgres:// URL",
+            POSTGRES_URL_VAR
+        );
+        pools.push(Pool::open(&url));
+    }
+    pools
+}
+
+macro_rules! conformance_tests {
+    ($($name:ident,)*) => {
+        $(
+            #[tokio::test]
+            async fn $name() {
+                for pool in pools() {
+                    let mut conn = pool.connection().await;
+                    let mut tx = conn.transaction().await;
+                    checks::$name(tx.conn()).await;
+                    tx.finish().await.unwrap();
+                }
+            }
+        )*
+    };
+}
+
+conformance_tests! {
+    artifacts,
+    index_refresh,
+    pstats,
+    self_profile,
+    errors,
+    compiler_metadata,
+    benchmark_sources,
+    regression_check,
+    aggregate_index,
+    bootstrap,
+    pull_request_queue,
+    pull_request_priority,
+    rollup_members,
+    collector_leases,
+    artifact_queue,
+    master_commits,
+    annotations,
+    collector_progress,
+    invalidate_artifact,
+    collection_duration,
+}
+
+fn master_commit(sha: &str) -> ArtifactId {
+    ArtifactId::Commit(Commit {
+        sha: sha.to_string(),
+        date: Date::ymd_hms(2020, 6, 1, 12, 0, 0),
+    })
+}
+
+fn try_commit(sha: &str) -> ArtifactId {
+    ArtifactId::Commit(Commit {
+        sha: sha.to_string(),
+        date: Date::ymd_hms(2000, 1, 1, 0, 0, 0),
+    })
+}
+
+mod checks {
+    use super::*;
+
+    pub async fn artifacts(conn: &mut dyn Connection) {
+        let master = master_commit("conformance-master");
+        let try_ = try_commit("conformance-try");
+        let release = ArtifactId::Artifact("conformance-1.0.0".to_string());
+
+        let master_id = conn.artifact_id(&master).await;
+        let try_id = conn.artifact_id(&try_).await;
+        let release_id = conn.artifact_id(&release).await;
+        assert_eq!(conn.artifact_id(&master).await, master_id);
+        assert_ne!(master_id, try_id);
+        assert_ne!(try_id, release_id);
+
+        match conn.artifact_by_name("conformance-master").await {
+            Some(ArtifactId::Commit(c)) => {
+                assert_eq!(c.sha, "conformance-master");
+                assert_eq!(c.date, Date::ymd_hms(2020, 6, 1, 12, 0, 0));
+            }
+            other => panic!("unexpected artifact: {:?}", other),
+        }
+        match conn.artifact_by_name("conformance-try").await {
+            Some(ArtifactId::Commit(c)) => assert!(c.is_try()),
+            other => panic!("unexpected artifact: {:?}", other),
+        }
+        assert_eq!(
+            conn.artifact_by_name("conformance-1.0.0").await,
+            Some(release.clone())
+        );
+        assert_eq!(conn.artifact_by_name("conformance-missing").await, None);
+
+        let index = Index::load(conn).await;
+        assert_eq!(master.lookup(&index), Some(master_id));
+        assert_eq!(try_.lookup(&index), Some(try_id));
+        assert_eq!(release.lookup(&index), Some(release_id));
+        assert!(index.artifacts().any(|a| a == "conformance-1.0.0"));
+        let commits = index.commits();
+        let try_pos = commits.iter().position(|c| c.sha == "conformance-try");
+        let master_pos = commits.iter().position(|c| c.sha == "conformance-master");
+        assert!(try_pos.is_some() && master_pos.is_some());
+        assert!(
+            try_pos < master_pos,
+            "try commits sort before master commits"
+        );
+    }
+
+    pub async fn index_refresh(conn: &mut dyn Connection) {
+        let krate = "conformance-refresh";
+        conn.record_benchmark(krate, None).await;
+        let index = Index::load(conn).await;
+        assert_eq!(index.watermark(), conn.index_watermark().await);
+        assert_eq!(index.refresh(conn).await, None);
+
+        let artifact = master_commit("conformance-refresh");
+        let aid = conn.artifact_id(&artifact).await;
+        let cid = conn.collection_id("conformance").await;
+        conn.record_statistic(
+            cid,
+            aid,
+            krate,
+            Profile::Debug,
+            Cache::IncrementalEmpty,
+            "wall-time",
+            1.0,
+        )
+        .await;
+        conn.record_error(aid, krate, "error").await;
+
+        let delta = conn.load_index_since(&index.watermark()).await;
+        assert_eq!(
+            delta.commits(),
+            vec![Commit {
+                sha: "conformance-refresh".to_string(),
+                date: Date::ymd_hms(2020, 6, 1, 12, 0, 0),
+            }]
+        );
+        assert_eq!(
+            delta.all_errors().collect::<Vec<_>>(),
+            vec![Crate::from(krate)]
+        );
+        assert_eq!(delta.all_pstat_series().count(), 1);
+
+        let refreshed = index.refresh(conn).await.expect("index changed");
+        assert_eq!(refreshed, Index::load(conn).await);
+        assert_eq!(artifact.lookup(&refreshed), Some(aid));
+    }
+
+    pub async fn pstats(conn: &mut dyn Connection) {
+        let krate = "conformance-pstats";
+        let artifact = master_commit("conformance-pstats");
+        let aid = conn.artifact_id(&artifact).await;
+        let other = conn
+            .artifact_id(&master_commit("conformance-pstats-other"))
+            .await;
+        conn.record_benchmark(krate, None).await;
+        conn.record_benchmark(krate, Some(true)).await;
+
+        let first = conn.collection_id("conformance\n").await;
+        let second = conn.collection_id("conformance").await;
+        assert_ne!(first, second);
+        for (cid, value) in &[(first, 20.0), (second, 10.0)] {
+            conn.record_statistic(
+                *cid,
+                aid,
+                krate,
+                Profile::Check,
+                Cache::Empty,
+                "instructions:u",
+                *value,
+            )
+            .await;
+        }
+
+        let index = Index::load(conn).await;
+        assert!(index.stats().contains(&"instructions:u".to_string()));
+        let label = DbLabel::ProcessStat {
+            krate: Crate::from(krate),
+            profile: Profile::Check,
+            cache: Cache::Empty,
+            stat: ProcessStatistic::from("instructions:u"),
+        };
+        let series = label.lookup(&index).expect("series is indexed");
+        assert_eq!(
+            conn.get_pstats(&[series], &[Some(aid), None, Some(other)])
+                .await,
+            vec![vec![Some(10.0), None, None]]
+        );
+        assert_eq!(index.get::<f64>(conn, &label, &artifact).await, Some(10.0));
+    }
+
+    pub async fn self_profile(conn: &mut dyn Connection) {
+        let krate = "conformance-self-profile";
+        let artifact = master_commit("conformance-self-profile");
+        let aid = conn.artifact_id(&artifact).await;
+        conn.record_benchmark(krate, Some(false)).await;
+        let cid = conn.collection_id("conformance").await;
+
+        let qd = QueryDatum {
+            self_time: Duration::from_nanos(1_500),
+            blocked_time: Duration::from_nanos(20),
+            incremental_load_time: Duration::from_nanos(300),
+            number_of_cache_hits: 4,
+            invocation_count: 5,
+        };
+        conn.record_self_profile_query(
+            cid,
+            aid,
+            krate,
+            Profile::Opt,
+            Cache::IncrementalFresh,
+            "typeck",
+            qd.clone(),
+        )
+        .await;
+
+        let profile = conn
+            .get_self_profile(aid, krate, "opt", "incr-unchanged")
+            .await;
+        assert_eq!(profile.len(), 1);
+        assert_eq!(profile.get(&QueryLabel::from("typeck")), Some(&qd));
+        assert!(conn
+            .get_self_profile(aid, krate, "check", "incr-unchanged")
+            .await
+            .is_empty());
+
+        let index = Index::load(conn).await;
+        let label = DbLabel::SelfProfileQuery {
+            krate: Crate::from(krate),
+            profile: Profile::Opt,
+            cache: Cache::IncrementalFresh,
+            query: QueryLabel::from("typeck"),
+        };
+        assert_eq!(
+            index.get::<QueryDatum>(conn, &label, &artifact).await,
+            Some(qd)
+        );
+        assert_eq!(
+            index
+                .filtered_queries(Crate::from(krate), Profile::Opt, Cache::IncrementalFresh)
+                .collect::<Vec<_>>(),
+            vec![QueryLabel::from("typeck")]
+        );
+
+        conn.record_raw_self_profile(cid, aid, krate, Profile::Opt, Cache::IncrementalFresh)
+            .await;
+        assert_eq!(
+            conn.list_self_profile(artifact.clone(), krate, "opt", "incr-unchanged")
+                .await,
+            vec![(aid, cid.to_string().parse::<i32>().unwrap())]
+        );
+        assert!(conn
+            .list_self_profile(artifact, krate, "check", "incr-unchanged")
+            .await
+            .is_empty());
+    }
+
+    pub async fn errors(conn: &mut dyn Connection) {
+        let krate = "conformance-errors";
+        let aid = conn.artifact_id(&master_commit("conformance-errors")).await;
+        let other = conn
+            .artifact_id(&master_commit("conformance-errors-other"))
+            .await;
+        conn.record_benchmark(krate, None).await;
+        conn.record_error(aid, krate, "failed to build").await;
+
+        assert_eq!(
+            conn.get_error(aid).await.get(krate),
+            Some(&Some("failed to build".to_string()))
+        );
+        assert_eq!(conn.get_error(other).await.get(krate), Some(&None));
+        assert_eq!(
+            conn.get_errors(&[other, aid]).await.get(krate),
+            Some(&vec![None, Some("failed to build".to_string())])
+        );
+
+        let index = Index::load(conn).await;
+        assert!(index.all_errors().any(|c| c == Crate::from(krate)));
+    }
+
+    pub async fn compiler_metadata(conn: &mut dyn Connection) {
+        let aid = conn
+            .artifact_id(&master_commit("conformance-compiler"))
+            .await;
+        assert_eq!(conn.get_compiler_metadata(aid).await, None);
+
+        let mut metadata = CompilerMetadata {
+            version: "rustc 1.48.0-nightly (0da580074 2020-09-22)".to_string(),
+            commit_hash: Some("0da58007451a154da2480160429e1604a1f5f0ec".to_string()),
+            llvm_version: Some("11.0.0".to_string()),
+            host: "x86_64-unknown-linux-gnu".to_string(),
+        };
+        conn.record_compiler_metadata(aid, &metadata).await;
+        assert_eq!(
+            conn.get_compiler_metadata(aid).await,
+            Some(metadata.clone())
+        );
+
+        // Recording again replaces the previous metadata.
+        metadata.commit_hash = None;
+        metadata.llvm_version = None;
+        conn.record_compiler_metadata(aid, &metadata).await;
+        assert_eq!(conn.get_compiler_metadata(aid).await, Some(metadata));
+    }
+
+    pub async fn benchmark_sources(conn: &mut dyn Connection) {
+        let first = conn
+            .artifact_id(&master_commit("conformance-source-first"))
+            .await;
+        let second = conn
+            .artifact_id(&master_commit("conformance-source-second"))
+            .await;
+        conn.record_benchmark("conformance-source", None).await;
+        conn.record_benchmark_source(first, "conformance-source", "aaaa")
+            .await;
+
+        let sources = conn.get_benchmark_sources(&[first, second]).await;
+        assert_eq!(
+            sources.get("conformance-source"),
+            Some(&vec![Some("aaaa".to_string()), None])
+        );
+
+        // Recording again replaces the previous hash.
+        conn.record_benchmark_source(first, "conformance-source", "bbbb")
+            .await;
+        conn.record_benchmark_source(second, "conformance-source", "cccc")
+            .await;
+        let sources = conn.get_benchmark_sources(&[second, first]).await;
+        assert_eq!(
+            sources.get("conformance-source"),
+            Some(&vec![Some("cccc".to_string()), Some("bbbb".to_string())])
+        );
+    }
+
+    pub async fn regression_check(conn: &mut dyn Connection) {
+        let aid = conn
+            .artifact_id(&master_commit("conformance-regression"))
+            .await;
+        assert_eq!(conn.regression_check(aid).await, None);
+
+        assert!(conn.record_regression_check(aid, Some(42), true).await);
+        assert_eq!(conn.regression_check(aid).await, Some(true));
+
+        // Only the first outcome is kept.
+        assert!(!conn.record_regression_check(aid, None, false).await);
+        assert_eq!(conn.regression_check(aid).await, Some(true));
+    }
+
+    pub async fn aggregate_index(conn: &mut dyn Connection) {
+        let aid = conn
+            .artifact_id(&master_commit("conformance-aggregate"))
+            .await;
+        let other = conn
+            .artifact_id(&master_commit("conformance-aggregate-other"))
+            .await;
+        let baseline = conn
+            .artifact_id(&master_commit("conformance-aggregate-baseline"))
+            .await;
+        conn.record_aggregate_index("conformance", aid, baseline, Some(1.5))
+            .await;
+        conn.record_aggregate_index("conformance", other, baseline, Some(-2.0))
+            .await;
+        conn.record_aggregate_index("conformance-other", aid, baseline, Some(3.0))
+            .await;
+        conn.record_aggregate_index("conformance-other", other, other, None)
+            .await;
+
+        // Recording a value again replaces it.
+        conn.record_aggregate_index("conformance", aid, baseline, Some(0.5))
+            .await;
+        let values = conn.aggregate_index("conformance").await;
+        assert_eq!(values.len(), 2);
+        assert_eq!(values.get(&aid), Some(&Some(0.5)));
+        assert_eq!(values.get(&other), Some(&Some(-2.0)));
+        // A missing value is recorded as such.
+        let values = conn.aggregate_index("conformance-other").await;
+        assert_eq!(values.get(&other), Some(&None));
+
+        // Invalidating an artifact drops its values of every index.
+        conn.invalidate_artifact(aid, None).await;
+        assert_eq!(conn.aggregate_index("conformance").await.get(&aid), None);
+        assert_eq!(conn.aggregate_index("conformance-other").await.len(), 1);
+
+        // Invalidating a baseline drops the values relative to it.
+        conn.invalidate_artifact(baseline, None).await;
+        assert!(conn.aggregate_index("conformance").await.is_empty());
+        assert_eq!(conn.aggregate_index("conformance-other").await.len(), 1);
+    }
+
+    pub async fn bootstrap(conn: &mut dyn Connection) {
+        let aid = conn
+            .artifact_id(&master_commit("conformance-bootstrap"))
+            .await;
+        let other = conn
+            .artifact_id(&master_commit("conformance-bootstrap-other"))
+            .await;
+        let first = conn.collection_id("conformance").await;
+        let second = conn.collection_id("conformance").await;
+        conn.record_rustc_crate(first, aid, "conformance_crate", Duration::from_millis(30))
+            .await;
+        conn.record_rustc_crate(second, aid, "conformance_crate", Duration::from_millis(20))
+            .await;
+
+        let bootstrap = conn.get_bootstrap(&[aid, other]).await;
+        assert_eq!(
+            bootstrap.get("conformance_crate"),
+            Some(&vec![Some(Duration::from_millis(20)), None])
+        );
+        let bootstrap = conn.get_bootstrap(&[other, aid]).await;
+        assert_eq!(
+            bootstrap.get("conformance_crate"),
+            Some(&vec![None, Some(Duration::from_millis(20))])
+        );
+    }
+
+    pub async fn pull_request_queue(conn: &mut dyn Connection) {
+        let pr = 999_000_026;
+        let sha = "conformance-try-sha";
+        let parent = "conformance-parent-sha";
+        let expected = || QueuedCommit {
+            pr,
+            sha: sha.to_string(),
+            parent_sha: parent.to_string(),
+            include: Some("syn".to_string()),
+            exclude: None,
+            runs: Some(2),
+            builds: Some("Check,Debug".to_string()),
+            scenarios: None,
+            high_priority: false,
+        };
+
+        conn.queue_pr(
+            pr,
+            Some("syn"),
+            None,
+            Some(2),
+            Some("Check,Debug"),
+            None,
+            false,
+        )
+        .await;
+        // Queuing a PR which is already waiting for a commit is a no-op.
+        conn.queue_pr(pr, None, Some("regex"), None, None, Some("Full"), true)
+            .await;
+        assert!(!conn.queued_commits().await.iter().any(|c| c.pr == pr));
+
+        assert!(conn.pr_attach_commit(pr, sha, parent).await);
+        assert!(
+            !conn
+                .pr_attach_commit(pr, "conformance-other-sha", parent)
+                .await
+        );
+        let queued = conn
+            .queued_commits()
+            .await
+            .into_iter()
+            .filter(|c| c.pr == pr)
+            .collect::<Vec<_>>();
+        assert_eq!(queued, vec![expected()]);
+
+        assert_eq!(conn.parent_of(sha).await, Some(parent.to_string()));
+        assert_eq!(conn.pr_of(sha).await, Some(pr));
+        assert_eq!(conn.parent_of("conformance-missing").await, None);
+        assert_eq!(conn.pr_of("conformance-missing").await, None);
+
+        assert_eq!(conn.mark_complete(sha).await, Some(expected()));
+        assert!(!conn.queued_commits().await.iter().any(|c| c.pr == pr));
+        assert_eq!(conn.mark_complete("conformance-missing").await, None);
+
+        // Once the previous build completed the PR can be queued again.
+        conn.queue_pr(pr, None, None, None, None, None, false).await;
+        assert!(
+            conn.pr_attach_commit(pr, "conformance-other-sha", sha)
+                .await
+        );
+
+        // Cancelling only removes the build which has not completed yet.
+        assert!(conn.cancel_pr(pr).await);
+        assert!(!conn.cancel_pr(pr).await);
+        assert!(!conn.queued_commits().await.iter().any(|c| c.pr == pr));
+        assert_eq!(conn.pr_of(sha).await, Some(pr));
+    }
+
+    pub async fn pull_request_priority(conn: &mut dyn Connection) {
+        let (normal, urgent) = (999_000_040, 999_000_041);
+        conn.queue_pr(normal, None, None, None, None, None, false)
+            .await;
+        conn.pr_attach_commit(normal, "conformance-normal-sha", "conformance-parent")
+            .await;
+        conn.queue_pr(urgent, None, None, None, None, None, true)
+            .await;
+        conn.pr_attach_commit(urgent, "conformance-urgent-sha", "conformance-parent")
+            .await;
+
+        let queued = conn
+            .queued_commits()
+            .await
+            .into_iter()
+            .filter(|c| c.pr == normal || c.pr == urgent)
+            .map(|c| (c.pr, c.high_priority))
+            .collect::<Vec<_>>();
+        assert_eq!(queued, vec![(urgent, true), (normal, false)]);
+    }
+
+    pub async fn rollup_members(conn: &mut dyn Connection) {
+        let rollup = 999_000_033;
+        let (first, second) = (999_000_034, 999_000_035);
+        let (first_perf, second_perf) = (999_000_036, 999_000_037);
+        let base = "conformance-rollup-base";
+        conn.record_rollup_member(rollup, 1, second, second_perf, base)
+            .await;
+        conn.record_rollup_member(rollup, 0, first, first_perf, base)
+            .await;
+
+        let unreported = rollup_members_of(conn, rollup).await;
+        assert_eq!(
+            unreported
+                .iter()
+                .map(|m| (m.position, m.rolled_up_pr, m.perf_pr))
+                .collect::<Vec<_>>(),
+            vec![(0, first, first_perf), (1, second, second_perf)]
+        );
+        assert!(unreported.iter().all(|m| m.try_build.is_none()));
+        assert!(unreported
+            .iter()
+            .all(|m| m.base_sha.as_deref() == Some(base)));
+
+        let sha = "conformance-rollup-sha";
+        let parent = "conformance-rollup-parent";
+        conn.queue_pr(first_perf, None, None, None, None, None, false)
+            .await;
+        conn.pr_attach_commit(first_perf, sha, parent).await;
+        conn.mark_complete(sha).await;
+        let unreported = rollup_members_of(conn, rollup).await;
+        assert_eq!(
+            unreported[0].try_build,
+            Some((sha.to_string(), parent.to_string()))
+        );
+        assert_eq!(unreported[1].try_build, None);
+
+        conn.mark_rollup_reported(rollup).await;
+        assert!(rollup_members_of(conn, rollup).await.is_empty());
+    }
+
+    pub async fn collector_leases(conn: &mut dyn Connection) {
+        let expiry = Duration::from_secs(600);
+        let (first, second) = ("conformance-lease-a", "conformance-lease-b");
+        assert!(conn.acquire_lease("collector-1", first, expiry).await);
+        // A live lease can't be taken over, but its holder can acquire it again.
+        assert!(!conn.acquire_lease("collector-2", first, expiry).await);
+        assert!(conn.acquire_lease("collector-1", first, expiry).await);
+        assert!(conn.renew_lease("collector-1", first).await);
+        assert!(!conn.renew_lease("collector-2", first).await);
+
+        let leases = conn.leases().await;
+        let lease = leases.iter().find(|l| l.artifact == first).unwrap();
+        assert_eq!(lease.collector, "collector-1");
+        assert!(lease.heartbeat >= lease.acquired);
+
+        // Moving on to another artifact releases the previous lease.
+        assert!(conn.acquire_lease("collector-1", second, expiry).await);
+        assert!(!conn.renew_lease("collector-1", first).await);
+        assert!(conn.acquire_lease("collector-2", first, expiry).await);
+
+        conn.release_lease("collector-1", second).await;
+        let leased = conn
+            .leases()
+            .await
+            .into_iter()
+            .filter(|l| l.artifact.starts_with("conformance-lease"))
+            .map(|l| (l.collector, l.artifact))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            leased,
+            vec![(String::from("collector-2"), first.to_string())]
+        );
+    }
+
+    pub async fn artifact_queue(conn: &mut dyn Connection) {
+        let release = "conformance-1.47.0";
+        let local = "conformance-local";
+        assert!(conn.queue_artifact(release, None).await);
+        assert!(conn.queue_artifact(local, Some("/tmp/rustc")).await);
+        // Queuing an artifact which is already waiting is a no-op.
+        assert!(!conn.queue_artifact(release, Some("/tmp/other")).await);
+
+        let mut queued = conformance_artifacts(conn).await;
+        queued.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            queued,
+            vec![
+                QueuedArtifact {
+                    name: release.to_string(),
+                    local_rustc: None,
+                },
+                QueuedArtifact {
+                    name: local.to_string(),
+                    local_rustc: Some("/tmp/rustc".to_string()),
+                },
+            ]
+        );
+
+        conn.mark_artifact_complete(release).await;
+        assert_eq!(
+            conformance_artifacts(conn)
+                .await
+                .into_iter()
+                .map(|a| a.name)
+                .collect::<Vec<_>>(),
+            vec![local.to_string()]
+        );
+
+        // Once out of the queue, an artifact can be queued again.
+        assert!(conn.queue_artifact(release, None).await);
+        assert_eq!(conformance_artifacts(conn).await.len(), 2);
+
+        // Requeueing keeps the toolchain it was queued with.
+        conn.mark_artifact_complete(local).await;
+        assert!(conn.requeue_artifact(local).await);
+        assert!(conformance_artifacts(conn).await.contains(&QueuedArtifact {
+            name: local.to_string(),
+            local_rustc: Some("/tmp/rustc".to_string()),
+        }));
+        assert!(!conn.requeue_artifact("conformance-never-queued").await);
+
+        // A failed artifact leaves the queue, and queueing it again clears
+        // the error.
+        conn.mark_artifact_failed(local, "no such file").await;
+        assert!(!conformance_artifacts(conn)
+            .await
+            .iter()
+            .any(|a| a.name == local));
+        assert!(conn.failed_artifacts().await.contains(&FailedArtifact {
+            name: local.to_string(),
+            error: "no such file".to_string(),
+        }));
+        assert!(conn.queue_artifact(local, Some("/tmp/fixed")).await);
+        assert!(!conn
+            .failed_artifacts()
+            .await
+            .iter()
+            .any(|a| a.name == local));
+    }
+
+    pub async fn master_commits(conn: &mut dyn Connection) {
+        let commit = |sha: &str, parent_sha: &str, pr, day| MasterCommit {
+            sha: sha.to_string(),
+            parent_sha: parent_sha.to_string(),
+            pr,
+            time: Utc.ymd(2020, 10, day).and_hms(12, 0, 0),
+        };
+        let first = commit("conformance-master-a", "conformance-master-0", Some(1), 1);
+        let second = commit("conformance-master-b", "conformance-master-a", None, 2);
+        conn.record_master_commits(&[first.clone(), second.clone()])
+            .await;
+
+        assert_eq!(
+            conn.master_commits()
+                .await
+                .into_iter()
+                .filter(|c| c.sha.starts_with("conformance-master"))
+                .collect::<Vec<_>>(),
+            vec![second.clone(), first]
+        );
+
+        // Refreshing the list updates existing entries, and drops the ones
+        // older than the refreshed list.
+        let second = MasterCommit {
+            pr: Some(2),
+            ..second
+        };
+        conn.record_master_commits(&[second.clone()]).await;
+        assert_eq!(
+            conn.master_commits()
+                .await
+                .into_iter()
+                .filter(|c| c.sha.starts_with("conformance-master"))
+                .collect::<Vec<_>>(),
+            vec![second]
+        );
+    }
+
+    pub async fn annotations(conn: &mut dyn Connection) {
+        let noisy = Annotation {
+            id: 0,
+            start: "conformance-annotation-a".to_string(),
+            end: "conformance-annotation-b".to_string(),
+            benchmark: None,
+            profile: None,
+            cache: None,
+            author: "conformance".to_string(),
+            text: "noisy machine".to_string(),
+            created: Date::ymd_hms(2020, 10, 1, 12, 0, 0),
+        };
+        let updated = Annotation {
+            start: "conformance-annotation-c".to_string(),
+            end: "conformance-annotation-c".to_string(),
+            benchmark: Some("syn".to_string()),
+            profile: Some("opt".to_string()),
+            text: "benchmark updated".to_string(),
+            ..noisy.clone()
+        };
+        let noisy = Annotation {
+            id: conn.add_annotation(&noisy).await,
+            ..noisy
+        };
+        let updated = Annotation {
+            id: conn.add_annotation(&updated).await,
+            ..updated
+        };
+        assert_ne!(noisy.id, updated.id);
+        assert_eq!(
+            conn.annotations().await,
+            vec![noisy.clone(), updated.clone()]
+        );
+
+        assert!(conn.delete_annotation(noisy.id).await);
+        assert!(!conn.delete_annotation(noisy.id).await);
+        assert_eq!(conn.annotations().await, vec![updated]);
+    }
+
+    async fn conformance_artifacts(conn: &mut dyn Connection) -> Vec<QueuedArtifact> {
+        conn.queued_artifacts()
+            .await
+            .into_iter()
+            .filter(|a| a.name.starts_with("conformance-"))
+            .collect()
+    }
+
+    async fn rollup_members_of(conn: &mut dyn Connection, rollup: u32) -> Vec<RollupMember> {
+        conn.unreported_rollup_members()
+            .await
+            .into_iter()
+            .filter(|m| m.rollup_pr == rollup)
+            .collect()
+    }
+
+    pub async fn collector_progress(conn: &mut dyn Connection) {
+        let artifact = master_commit("conformance-progress");
+        let aid = conn.artifact_id(&artifact).await;
+        let steps = vec!["conformance-a".to_string(), "conformance-b".to_string()];
+
+        conn.collector_start(aid, &steps).await;
+        assert_eq!(conn.in_progress_artifacts().await, vec![artifact.clone()]);
+
+        // Starting another artifact, e.g. on another collector, leaves this
+        // one in progress.
+        let other = master_commit("conformance-progress-other");
+        let other_aid = conn.artifact_id(&other).await;
+        conn.collector_start(other_aid, &steps).await;
+        assert_eq!(
+            conn.in_progress_artifacts().await,
+            vec![artifact.clone(), other.clone()]
+        );
+
+        assert!(conn.collector_start_step(aid, "conformance-a").await);
+        conn.collector_end_step(aid, "conformance-a").await;
+        // Finished steps are never started again.
+        assert!(!conn.collector_start_step(aid, "conformance-a").await);
+
+        let progress = conn.in_progress_steps(&artifact).await;
+        assert_eq!(
+            progress
+                .iter()
+                .map(|s| (s.name.as_str(), s.is_done))
+                .collect::<Vec<_>>(),
+            vec![("conformance-a", true), ("conformance-b", false)]
+        );
+
+        // Restarting the collection keeps the finished steps.
+        conn.collector_start(aid, &steps).await;
+        assert!(!conn.collector_start_step(aid, "conformance-a").await);
+        assert!(conn.collector_start_step(aid, "conformance-b").await);
+        conn.collector_end_step(aid, "conformance-b").await;
+        assert!(conn
+            .in_progress_steps(&artifact)
+            .await
+            .iter()
+            .all(|s| s.is_done));
+        assert_eq!(conn.in_progress_artifacts().await, vec![other]);
+    }
+
+    pub async fn invalidate_artifact(conn: &mut dyn Connection) {
+        let artifact = master_commit("conformance-invalidate");
+        let aid = conn.artifact_id(&artifact).await;
+        let cid = conn.collection_id("conformance").await;
+        let steps = vec![
+            "conformance-invalidate-a".to_string(),
+            "conformance-invalidate-b".to_string(),
+        ];
+        conn.collector_start(aid, &steps).await;
+        for step in &steps {
+            conn.record_benchmark(step, None).await;
+            assert!(conn.collector_start_step(aid, step).await);
+            conn.record_statistic(
+                cid,
+                aid,
+                step,
+                Profile::Check,
+                Cache::Empty,
+                "instructions:u",
+                1.0,
+            )
+            .await;
+            conn.record_error(aid, step, "failed to build").await;
+            conn.collector_end_step(aid, step).await;
+        }
+        assert!(!conn.in_progress_artifacts().await.contains(&artifact));
+
+        // Benchmarks which never ran for the artifact are ignored.
+        let requested = vec![steps[1].clone(), "conformance-invalidate-typo".to_string()];
+        let reset = conn.invalidate_artifact(aid, Some(&requested)).await;
+        assert_eq!(reset, vec![steps[1].clone()]);
+        assert_eq!(conn.in_progress_artifacts().await, vec![artifact.clone()]);
+        let errors = conn.get_error(aid).await;
+        assert_eq!(
+            errors.get(&steps[0]),
+            Some(&Some("failed to build".to_string()))
+        );
+        assert_eq!(errors.get(&steps[1]), Some(&None));
+
+        // A restarted collection only runs the invalidated steps, even when
+        // asked for more.
+        let mut more = steps.clone();
+        more.push("conformance-invalidate-c".to_string());
+        conn.collector_start(aid, &more).await;
+        assert!(!conn.collector_start_step(aid, &steps[0]).await);
+        assert!(!conn.collector_start_step(aid, &more[2]).await);
+        assert!(conn.collector_start_step(aid, &steps[1]).await);
+        conn.collector_end_step(aid, &steps[1]).await;
+        assert!(!conn.in_progress_artifacts().await.contains(&artifact));
+
+        // Invalidating everything resets every step with data.
+        assert_eq!(conn.invalidate_artifact(aid, None).await, steps);
+        assert!(conn
+            .get_error(aid)
+            .await
+            .values()
+            .all(|error| error.is_none()));
+        assert!(conn
+            .in_progress_steps(&artifact)
+            .await
+            .iter()
+            .all(|s| !s.is_done));
+    }
+
+    pub async fn collection_duration(conn: &mut dyn Connection) {
+        let aid = conn
+            .artifact_id(&master_commit("conformance-duration"))
+            .await;
+        conn.record_duration(aid, Duration::from_secs(60)).await;
+        assert!(conn.last_end_time().await.is_some());
+
+        // Creating indices is idempotent.
+        conn.maybe_create_indices().await;
+        conn.maybe_create_indices().await;
+    }
+}
@@ -0,0 +1,206 @@
+//! Aggregate indices summarize the performance of an artifact in a single
+//! number, relative to a baseline artifact, as configured in
+//! `site-config.toml` (see `AggregateIndex`).
+//!
+//! Every test case (benchmark, profile and cache state) with results for both
+//! artifacts contributes the ratio of its result to the baseline's. The
+//! ratios are combined with a geometric mean per benchmark category, and the
+//! categories with a weighted geometric mean, so that a category doesn't
+//! count for more just because it has more benchmarks.
+
+use crate::api::ServerResult;
+use crate::db::{ArtifactId, Crate};
+use crate::load::{AggregateIndex, InputData};
+use crate::selector::{self, Tag};
+use collector::Bound;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Computes and records the configured indices of the artifacts which don't
+/// have them yet. Artifacts which are still being benchmarked are skipped, as
+/// their results are incomplete.
+pub async fn update(data: &InputData) {
+    let conn = data.conn().await;
+    let in_progress = conn.in_progress_artifacts().await;
+    for index in &data.config.aggregate_indices {
+        let baseline = match data.data_for(true, Bound::Commit(index.baseline.clone())) {
+            Some(baseline) if !in_progress.contains(&baseline) => baseline,
+            _ => {
+                log::warn!(
+                    "baseline {} of aggregate index {} has not been benchmarked",
+                    index.baseline,
+                    index.name
+                );
+                continue;
+            }
+        };
+        let baseline_aid = conn.artifact_id(&baseline).await;
+        let recorded = conn.aggregate_index(&index.name).await;
+        for artifact in artifacts(data) {
+            if in_progress.contains(&artifact) {
+                continue;
+            }
+            let aid = conn.artifact_id(&artifact).await;
+            if recorded.contains_key(&aid) {
+                continue;
+            }
+            match compute(data, index, &baseline, &artifact).await {
+                // A missing value is recorded too, so that it isn't recomputed
+                // on every update.
+                Ok(value) => {
+                    conn.record_aggregate_index(&index.name, aid, baseline_aid, value)
+                        .await
+                }
+                Err(e) => log::error!(
+                    "failed to compute aggregate index {} of {}: {}",
+                    index.name,
+                    artifact,
+                    e
+                ),
+            }
+        }
+    }
+}
+
+/// All benchmarked artifacts: the named ones first, then the commits from
+/// oldest to newest.
+pub fn artifacts(data: &InputData) -> Vec<ArtifactId> {
+    let index = data.index.load();
+    index
+        .artifacts()
+        .map(|a| ArtifactId::Artifact(a.to_string()))
+        .chain(index.commits().into_iter().map(ArtifactId::Commit))
+        .collect()
+}
+
+/// The change of `artifact` relative to `baseline`, in percent, or `None` if
+/// they have no test cases in common.
+pub async fn compute(
+    data: &InputData,
+    index: &AggregateIndex,
+    baseline: &ArtifactId,
+    artifact: &ArtifactId,
+) -> ServerResult<Option<f64>> {
+    if artifact == baseline {
+        return Ok(Some(0.0));
+    }
+
+    let benchmarks = index
+        .categories
+        .iter()
+        .flat_map(|c| c.benchmarks.iter().cloned())
+        .collect::<Vec<_>>();
+    let query = selector::Query::new()
+        .set(Tag::Crate, selector::Selector::Subset(benchmarks))
+        .set::<String>(Tag::Profile, selector::Selector::All)
+        .set::<String>(Tag::Cache, selector::Selector::All)
+        .set(
+            Tag::ProcessStatistic,
+            selector::Selector::One(index.stat.clone()),
+        );
+    let responses = data
+        .query::<Option<f64>>(query, Arc::new(vec![baseline.clone(), artifact.clone()]))
+        .await?;
+
+    // The logarithms of the ratios, by category.
+    let mut log_ratios: HashMap<&str, Vec<f64>> = HashMap::new();
+    for mut response in responses {
+        let krate = response.path.get::<Crate>()?.to_string();
+        let before = response.series.next().and_then(|(_, point)| point);
+        let after = response.series.next().and_then(|(_, point)| point);
+        let (before, after) = match (before, after) {
+            (Some(before), Some(after)) if before > 0.0 && after > 0.0 => (before, after),
+            _ => continue,
+        };
+        let category = index
+            .categories
+            .iter()
+            .find(|c| c.benchmarks.contains(&krate))
+            .unwrap();
+        log_ratios
+            .entry(category.name.as_str())
+            .or_default()
+            .push((after / before).ln());
+    }
+
+    Ok(combine(index, &log_ratios))
+}
+
+/// Combines the logarithms of the ratios, by category, into the change in
+/// percent, or `None` if no category has any.
+fn combine(index: &AggregateIndex, log_ratios: &HashMap<&str, Vec<f64>>) -> Option<f64> {
+    let mut total = 0.0;
+    let mut total_weight = 0.0;
+    for category in &index.categories {
+        if let Some(logs) = log_ratios.get(category.name.as_str()) {
+            total += category.weight * logs.iter().sum::<f64>() / logs.len() as f64;
+            total_weight += category.weight;
+        }
+    }
+    if total_weight == 0.0 {
+        return None;
+    }
+    Some(((total / total_weight).exp() - 1.0) * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::BenchmarkCategory;
+
+    fn index(weights: &[(&str, f64)]) -> AggregateIndex {
+        AggregateIndex {
+            name: String::from("test"),
+            baseline: String::from("1.0.0"),
+            stat: String::from("instructions:u"),
+            categories: weights
+                .iter()
+                .map(|&(name, weight)| BenchmarkCategory {
+                    name: name.to_string(),
+                    weight,
+                    benchmarks: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn geometric_mean_per_category() {
+        let index = index(&[("a", 1.0), ("b", 1.0)]);
+        let mut log_ratios = HashMap::new();
+        // Ratios of 2 and 8 have a geometric mean of 4, which is averaged
+        // with the unchanged category: sqrt(4 * 1) = 2.
+        log_ratios.insert("a", vec![2f64.ln(), 8f64.ln()]);
+        log_ratios.insert("b", vec![1f64.ln()]);
+        assert_close(combine(&index, &log_ratios), 100.0);
+    }
+
+    #[test]
+    fn categories_are_weighted() {
+        let index = index(&[("a", 3.0), ("b", 1.0), ("c", 10.0)]);
+        let mut log_ratios = HashMap::new();
+        // (2^3 * (1/8)^1)^(1/4) = 1; "c" has no results and doesn't count.
+        log_ratios.insert("a", vec![2f64.ln()]);
+        log_ratios.insert("b", vec![(1.0f64 / 8.0).ln()]);
+        assert_close(combine(&index, &log_ratios), 0.0);
+
+        log_ratios.insert("b", vec![2f64.ln()]);
+        assert_close(combine(&index, &log_ratios), 100.0);
+    }
+
+    #[test]
+    fn no_results() {
+        let index = index(&[("a", 1.0)]);
+        assert_eq!(combine(&index, &HashMap::new()), None);
+    }
+}
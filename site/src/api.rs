@@ -13,7 +13,7 @@
 //!
 //! The responses are calculated in the server.rs file.
 
-use database::{Crate, Date};
+use database::{CompilerMetadata, Crate, Date};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
@@ -47,8 +47,14 @@ pub struct DateData {
     pub pr: Option<u32>,
     pub commit: String,
     pub data: HashMap<String, Vec<(String, f64)>>,
+    /// The values of further statistics requested alongside the primary one,
+    /// by statistic, in the same form as `data`.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub stats: HashMap<String, HashMap<String, Vec<(String, f64)>>>,
     // crate -> nanoseconds
     pub bootstrap: HashMap<String, u64>,
+    /// The `rustc -vV` details, if recorded by the collector.
+    pub compiler: Option<CompilerMetadata>,
 }
 
 pub type ServerResult<T> = StdResult<T, String>;
@@ -77,12 +83,40 @@ pub mod dashboard {
         pub println_incr_averages: Vec<f64>,
     }
 
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct AggregateIndex {
+        pub name: String,
+        pub baseline: String,
+        /// Percent change from the baseline, for each of the versions.
+        pub values: Vec<Option<f64>>,
+    }
+
     #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub struct Response {
         pub versions: Vec<String>,
         pub check: Cases,
         pub debug: Cases,
         pub opt: Cases,
+        pub indices: Vec<AggregateIndex>,
+    }
+}
+
+pub mod aggregate_indices {
+    use serde::Serialize;
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct AggregateIndex {
+        pub name: String,
+        pub baseline: String,
+        pub stat: String,
+        /// (artifact, percent change from the baseline); named artifacts
+        /// first, then commits from oldest to newest.
+        pub values: Vec<(String, f64)>,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Response {
+        pub indices: Vec<AggregateIndex>,
     }
 }
 
@@ -121,11 +155,29 @@ pub mod graph {
         pub end: Bound,
         pub stat: String,
         pub absolute: bool,
+        #[serde(default)]
+        pub resolution: Resolution,
+    }
+
+    /// How finely the points of a graph are spaced. With anything coarser
+    /// than `Commit`, all commits landing in the same UTC day or week
+    /// (starting on Monday) are aggregated into one point.
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+    pub enum Resolution {
+        Commit,
+        Day,
+        Week,
+    }
+
+    impl Default for Resolution {
+        fn default() -> Self {
+            Resolution::Commit
+        }
     }
 
     #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
     pub struct GraphData {
-        pub commit: u16,
+        pub commit: u32,
         pub absolute: f32,
         pub percent: f32,
         pub y: f32,
@@ -139,21 +191,175 @@ pub mod graph {
         pub max: HashMap<String, f32>,
         pub colors: Vec<String>,
         pub commits: Vec<String>,
+        /// The annotations covering any of the commits.
+        pub annotations: Vec<database::Annotation>,
     }
 
     #[derive(Debug, PartialEq, Clone, Serialize)]
     pub struct Series {
-        // y-values
+        // y-values; the median of each period when aggregating
         pub points: Vec<f32>,
-        // The index of interpolated coordinates
-        pub is_interpolated: HashSet<u16>,
+        // The smallest and largest value of each period when aggregating,
+        // empty otherwise
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub min: Vec<f32>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub max: Vec<f32>,
+        // The index of interpolated coordinates; an aggregated point is
+        // interpolated if all of the points it covers are
+        pub is_interpolated: HashSet<u32>,
     }
 
     #[derive(Debug, PartialEq, Clone, Serialize)]
     pub struct NewResponse {
-        // (UTC timestamp in seconds, sha)
+        // (UTC timestamp in seconds, sha); when aggregating, the start of
+        // each period and the last commit in it
         pub commits: Vec<(i64, String)>,
         pub benchmarks: HashMap<String, HashMap<database::Profile, HashMap<String, Series>>>,
+        /// The indices of the commits (or periods) at which a benchmark's
+        /// source changed.
+        pub source_changes: HashMap<String, Vec<u32>>,
+        /// The annotations covering any of the commits.
+        pub annotations: Vec<database::Annotation>,
+    }
+}
+
+pub mod change_points {
+    use collector::Bound;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Request {
+        pub start: Bound,
+        pub end: Bound,
+        pub stat: String,
+        /// Changes smaller than this, in percent, are left out. Defaults to
+        /// 1%.
+        #[serde(default)]
+        pub min_percent: Option<f64>,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ChangePoint {
+        pub benchmark: String,
+        pub profile: String,
+        pub cache: String,
+        /// The first master commit whose results differ from the old level.
+        pub commit: String,
+        /// The PR which landed `commit`, if known.
+        pub pr: Option<u32>,
+        /// The first master commit at the new level. For gradual changes this
+        /// is later than `commit`.
+        pub end_commit: String,
+        /// Mean value before `commit`.
+        pub before: f64,
+        /// Mean value from `end_commit` on, until the next change.
+        pub after: f64,
+        pub percent: f64,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Response {
+        /// Largest changes first.
+        pub changes: Vec<ChangePoint>,
+    }
+}
+
+pub mod compare_many {
+    use collector::Bound;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Request {
+        pub baseline: Bound,
+        /// The artifacts to compare against the baseline, e.g. several
+        /// `bench_local` runs.
+        pub artifacts: Vec<Bound>,
+        pub stat: String,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Row {
+        pub benchmark: String,
+        pub profile: String,
+        pub cache: String,
+        pub baseline: Option<f64>,
+        /// The value of each artifact, in the order of `Response::artifacts`.
+        pub values: Vec<Option<f64>>,
+        /// The change of each value from the baseline, in percent.
+        pub percents: Vec<Option<f64>>,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Response {
+        /// The commit sha or artifact name of the baseline.
+        pub baseline: String,
+        pub artifacts: Vec<String>,
+        /// Sorted by benchmark, profile and cache state.
+        pub rows: Vec<Row>,
+    }
+}
+
+pub mod query {
+    use collector::Bound;
+    use serde::{Deserialize, Serialize};
+
+    /// Selects one or several values of a tag. Leaving a filter out (or
+    /// setting it to `null`) selects all values.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(untagged)]
+    pub enum Filter {
+        One(String),
+        Subset(Vec<String>),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Format {
+        Json,
+        Csv,
+    }
+
+    impl Default for Format {
+        fn default() -> Self {
+            Format::Json
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Request {
+        pub start: Bound,
+        pub end: Bound,
+        pub benchmark: Option<Filter>,
+        pub profile: Option<Filter>,
+        pub cache: Option<Filter>,
+        /// The statistic, e.g. `instructions:u`.
+        pub stat: Option<Filter>,
+        /// The self-profile query (e.g. `typeck`) whose self time to return
+        /// instead of a statistic.
+        pub query: Option<Filter>,
+        #[serde(default)]
+        pub format: Format,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Series {
+        pub benchmark: String,
+        pub profile: String,
+        pub cache: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub stat: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub query: Option<String>,
+        /// One value for each of the artifacts, if it was recorded.
+        pub values: Vec<Option<f64>>,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Response {
+        /// The commit shas, from oldest to newest.
+        pub artifacts: Vec<String>,
+        pub series: Vec<Series>,
     }
 }
 
@@ -179,7 +385,9 @@ pub mod bootstrap {
 
 pub mod days {
     use crate::api::DateData;
+    use crate::comparison::StatSummary;
     use collector::Bound;
+    use database::QueryLabel;
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -187,7 +395,38 @@ pub mod days {
         pub start: Bound,
         pub end: Bound,
 
+        /// The statistic whose values are returned in `DateData::data`.
         pub stat: String,
+        /// Further statistics to compare, whose values are returned in
+        /// `DateData::stats`.
+        #[serde(default)]
+        pub stats: Vec<String>,
+        /// Whether to attribute the largest significant changes in `stat` to
+        /// self-profile queries.
+        #[serde(default)]
+        pub self_profile: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct QueryChange {
+        pub label: QueryLabel,
+        /// Self time in `a` and `b`, in seconds.
+        pub self_time: (f64, f64),
+        pub invocation_count: (u32, u32),
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct QueryChanges {
+        /// The benchmark and profile, e.g. `syn-opt`.
+        pub benchmark: String,
+        pub cache: String,
+        /// The change in `stat`, in percent.
+        pub percent: f64,
+        /// The queries whose self time changed the most, largest change first.
+        pub by_self_time: Vec<QueryChange>,
+        /// The queries whose invocation count changed the most, largest change
+        /// first.
+        pub by_invocation_count: Vec<QueryChange>,
     }
 
     #[derive(Debug, Clone, Serialize)]
@@ -204,6 +443,21 @@ pub mod days {
         /// If `a` and `b` are adjacent artifacts (i.e., `a` is the parent of
         /// `b`).
         pub is_contiguous: bool,
+
+        /// The benchmarks whose source differs between `a` and `b`.
+        pub source_changed: Vec<String>,
+
+        /// The annotations covering any of the artifacts from `a` to `b`.
+        pub annotations: Vec<database::Annotation>,
+
+        /// The significant changes of each requested statistic, starting with
+        /// `stat`.
+        pub summaries: Vec<StatSummary>,
+
+        /// The self-profile queries behind the largest significant changes in
+        /// `stat`, if requested.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub query_changes: Vec<QueryChanges>,
     }
 }
 
@@ -233,20 +487,142 @@ pub mod status {
     #[derive(Serialize, Debug)]
     pub struct CurrentState {
         pub artifact: ArtifactId,
+        /// The collector holding the lease on the artifact, if any.
+        pub collector: Option<String>,
+        // None if not leased, otherwise seconds since epoch
+        pub last_heartbeat: Option<i64>,
+        /// Whether the lease expired, i.e. the collector appears to have
+        /// stopped and the artifact will be handed to the next collector.
+        pub lease_expired: bool,
         pub progress: Vec<Step>,
     }
 
+    #[derive(Serialize, Debug)]
+    pub struct FailedArtifact {
+        pub name: String,
+        pub error: String,
+    }
+
     #[derive(Serialize, Debug)]
     pub struct Response {
         pub last_commit: Option<Commit>,
         pub benchmarks: Vec<BenchmarkStatus>,
-        pub missing: Vec<(Commit, MissingReason)>,
-        pub current: Option<CurrentState>,
+        pub missing: Vec<(ArtifactId, MissingReason)>,
+        /// The artifacts being benchmarked, one per collector.
+        pub current: Vec<CurrentState>,
+        /// Queued artifacts which could not be benchmarked, and were taken out
+        /// of the queue.
+        pub failed_artifacts: Vec<FailedArtifact>,
         // None if no recent end, otherwise seconds since epoch
         pub most_recent_end: Option<i64>,
     }
 }
 
+pub mod queue_artifact {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Request {
+        /// The name the results are recorded under, e.g. `1.47.0`.
+        pub name: String,
+        /// The path to a rustc on the collector's machine to benchmark, for
+        /// custom toolchains. Releases are downloaded if this is not set.
+        pub local_rustc: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Response {
+        /// False if the artifact was already queued.
+        pub queued: bool,
+    }
+}
+
+pub mod invalidate {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Request {
+        /// The sha of the commit, or the name of the artifact.
+        pub artifact: String,
+        /// The benchmarks to re-run, or all of them if not set.
+        pub benchmarks: Option<Vec<String>>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Response {
+        /// The benchmarks whose results were deleted and which will be re-run.
+        pub steps: Vec<String>,
+    }
+}
+
+pub mod annotate {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Request {
+        /// The first commit sha or artifact name covered.
+        pub start: String,
+        /// The last commit sha or artifact name covered, `start` if not set.
+        pub end: Option<String>,
+        /// Restrict the annotation to a benchmark, profile (e.g. `opt`) or
+        /// cache state (e.g. `incr-patched: println`).
+        pub benchmark: Option<String>,
+        pub profile: Option<String>,
+        pub cache: Option<String>,
+        pub author: String,
+        pub text: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Response {
+        pub id: u32,
+    }
+}
+
+pub mod delete_annotation {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Request {
+        pub id: u32,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Response {
+        pub deleted: bool,
+    }
+}
+
+pub mod error_history {
+    use database::Commit;
+    use serde::Serialize;
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct BenchmarkErrors {
+        pub name: String,
+        /// The first master commit of the most recent run of failures.
+        pub first_failure: Commit,
+        /// The PR which landed `first_failure`, if known.
+        pub pr: Option<u32>,
+        /// The first master commit after the failures at which the benchmark
+        /// built again, or None if it is still failing.
+        pub recovered: Option<Commit>,
+        /// Number of consecutive failing commits.
+        pub failures: usize,
+        /// The error with paths, line numbers and hashes removed, so that
+        /// repeated failures for the same reason compare equal.
+        pub signature: String,
+        /// The full error at `first_failure`.
+        pub error: String,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Response {
+        /// Still failing benchmarks first, then most recent failures first.
+        pub benchmarks: Vec<BenchmarkErrors>,
+    }
+}
+
 pub mod self_profile_raw {
     use serde::{Deserialize, Serialize};
 
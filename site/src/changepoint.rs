@@ -0,0 +1,251 @@
+//! Detection of level shifts ("change points") in benchmark time series.
+//!
+//! This uses binary segmentation: the series is recursively split at the
+//! point which reduces the squared error around the segment means the most,
+//! as long as that reduction exceeds a penalty derived from the series' noise.
+//! Gradual changes show up as several small shifts in the same direction in
+//! quick succession, which are merged into one.
+
+/// The minimum number of points on each side of a shift. This keeps single
+/// outliers from being reported as two shifts.
+pub const MIN_SEGMENT: usize = 3;
+
+/// Consecutive shifts in the same direction at most this many points apart
+/// are considered to be one gradual change.
+const GRADUAL_GAP: usize = 10;
+
+/// How many times the noise variance (scaled by the log of the series length)
+/// a split has to explain to be accepted. Larger values find fewer shifts.
+const PENALTY_FACTOR: f64 = 3.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shift {
+    /// The index of the first point at which the level changes.
+    pub index: usize,
+    /// The index of the first point at the final level. This is `index` for
+    /// a step, and later for a gradual change.
+    pub end: usize,
+    /// Mean of the segment before the shift.
+    pub before: f64,
+    /// Mean of the segment starting at `end`.
+    pub after: f64,
+}
+
+impl Shift {
+    /// Relative change, in percent.
+    pub fn percent(&self) -> f64 {
+        (self.after - self.before) / self.before * 100.0
+    }
+}
+
+/// Finds the points at which the mean of `values` shifts, in order.
+///
+/// Series containing non-finite values have no meaningful mean, so none are
+/// found in them; callers should leave such points out.
+pub fn detect(values: &[f64]) -> Vec<Shift> {
+    let n = values.len();
+    if n < 2 * MIN_SEGMENT || values.iter().any(|v| !v.is_finite()) {
+        return Vec::new();
+    }
+
+    // Remove isolated outliers, which would otherwise show up as a pair of
+    // opposite shifts, while keeping steps intact. The noise is estimated
+    // before smoothing, which would hide most of it.
+    let noise = noise(values);
+    let smoothed = median_of_three(values);
+    let sums = PrefixSums::new(&smoothed);
+    let sigma = noise.max(sums.mean(0, n).abs() * 1e-6);
+    if sigma == 0.0 {
+        // A constant series.
+        return Vec::new();
+    }
+    let penalty = PENALTY_FACTOR * sigma * sigma * (n as f64).ln();
+
+    let mut splits = Vec::new();
+    let mut segments = vec![(0, n)];
+    while let Some((start, end)) = segments.pop() {
+        let cost = sums.cost(start, end);
+        let best = (start + MIN_SEGMENT..=end - MIN_SEGMENT)
+            .map(|split| (split, sums.cost(start, split) + sums.cost(split, end)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        if let Some((split, split_cost)) = best {
+            if cost - split_cost > penalty {
+                splits.push(split);
+                if split - start >= 2 * MIN_SEGMENT {
+                    segments.push((start, split));
+                }
+                if end - split >= 2 * MIN_SEGMENT {
+                    segments.push((split, end));
+                }
+            }
+        }
+    }
+    splits.sort();
+
+    let mut bounds = Vec::with_capacity(splits.len() + 2);
+    bounds.push(0);
+    bounds.extend(splits);
+    bounds.push(n);
+    let steps = bounds.windows(3).map(|w| Shift {
+        index: w[1],
+        end: w[1],
+        before: sums.mean(w[0], w[1]),
+        after: sums.mean(w[1], w[2]),
+    });
+
+    let mut shifts: Vec<Shift> = Vec::new();
+    for step in steps {
+        if let Some(last) = shifts.last_mut() {
+            let same_direction = (last.after > last.before) == (step.after > step.before);
+            if same_direction && step.index - last.end <= GRADUAL_GAP {
+                last.end = step.end;
+                last.after = step.after;
+                continue;
+            }
+        }
+        shifts.push(step);
+    }
+    shifts
+}
+
+fn median_of_three(values: &[f64]) -> Vec<f64> {
+    let mut smoothed = values.to_vec();
+    for (i, w) in values.windows(3).enumerate() {
+        let mut w = [w[0], w[1], w[2]];
+        w.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        smoothed[i + 1] = w[1];
+    }
+    smoothed
+}
+
+/// A robust estimate of the standard deviation of the noise in `values`,
+/// based on the median absolute difference between consecutive points so
+/// that the shifts themselves barely affect it.
+fn noise(values: &[f64]) -> f64 {
+    let mut diffs = values
+        .windows(2)
+        .map(|w| (w[1] - w[0]).abs())
+        .collect::<Vec<_>>();
+    diffs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = diffs[diffs.len() / 2];
+    // For normally distributed noise, the MAD is 0.6745 standard deviations,
+    // and differences have sqrt(2) times the noise's standard deviation.
+    median / (0.6745 * std::f64::consts::SQRT_2)
+}
+
+struct PrefixSums {
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl PrefixSums {
+    fn new(values: &[f64]) -> PrefixSums {
+        let mut sum = Vec::with_capacity(values.len() + 1);
+        let mut sum_sq = Vec::with_capacity(values.len() + 1);
+        sum.push(0.0);
+        sum_sq.push(0.0);
+        for v in values {
+            sum.push(sum.last().unwrap() + v);
+            sum_sq.push(sum_sq.last().unwrap() + v * v);
+        }
+        PrefixSums { sum, sum_sq }
+    }
+
+    fn mean(&self, start: usize, end: usize) -> f64 {
+        (self.sum[end] - self.sum[start]) / (end - start) as f64
+    }
+
+    /// The sum of squared deviations from the mean of `start..end`.
+    fn cost(&self, start: usize, end: usize) -> f64 {
+        let sum = self.sum[end] - self.sum[start];
+        let sum_sq = self.sum_sq[end] - self.sum_sq[start];
+        (sum_sq - sum * sum / (end - start) as f64).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A deterministic, slightly noisy series around `level`.
+    fn noisy(level: f64, len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|i| level + [0.3, -0.2, 0.1, -0.4, 0.2][i % 5])
+            .collect()
+    }
+
+    #[test]
+    fn single_step() {
+        let mut values = noisy(100.0, 20);
+        values.extend(noisy(110.0, 20));
+        let shifts = detect(&values);
+        assert_eq!(shifts.len(), 1, "{:?}", shifts);
+        assert_eq!(shifts[0].index, 20);
+        assert_eq!(shifts[0].end, 20);
+        assert!((shifts[0].percent() - 10.0).abs() < 0.5, "{:?}", shifts);
+    }
+
+    #[test]
+    fn gradual_drift() {
+        // Drifting by less than the noise over the whole series.
+        let values = noisy(100.0, 60)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| v + i as f64 * 0.005)
+            .collect::<Vec<_>>();
+        assert_eq!(detect(&values), Vec::new());
+    }
+
+    #[test]
+    fn gradual_steps_are_merged() {
+        // Four steps in the same direction, closer together than GRADUAL_GAP.
+        let mut values = noisy(100.0, 20);
+        for step in 1..=4 {
+            values.extend(noisy(100.0 + 3.0 * step as f64, 4));
+        }
+        values.extend(noisy(112.0, 16));
+        let shifts = detect(&values);
+        assert_eq!(shifts.len(), 1, "{:?}", shifts);
+        assert_eq!(shifts[0].index, 20);
+        assert_eq!(shifts[0].end, 32);
+        assert!((shifts[0].before - 100.0).abs() < 0.5, "{:?}", shifts);
+        assert!((shifts[0].after - 112.0).abs() < 0.5, "{:?}", shifts);
+    }
+
+    #[test]
+    fn distant_steps_are_separate() {
+        let mut values = noisy(100.0, 20);
+        values.extend(noisy(110.0, 2 * GRADUAL_GAP));
+        values.extend(noisy(120.0, 20));
+        let shifts = detect(&values);
+        assert_eq!(
+            shifts.iter().map(|s| (s.index, s.end)).collect::<Vec<_>>(),
+            vec![(20, 20), (20 + 2 * GRADUAL_GAP, 20 + 2 * GRADUAL_GAP)]
+        );
+    }
+
+    #[test]
+    fn single_outlier() {
+        let mut values = noisy(100.0, 40);
+        values[20] = 150.0;
+        assert_eq!(detect(&values), Vec::new());
+    }
+
+    #[test]
+    fn constant() {
+        assert_eq!(detect(&[100.0; 40]), Vec::new());
+    }
+
+    #[test]
+    fn too_short() {
+        assert_eq!(detect(&[100.0, 100.0, 100.0, 200.0, 200.0]), Vec::new());
+    }
+
+    #[test]
+    fn non_finite() {
+        let mut values = noisy(100.0, 20);
+        values.extend(noisy(110.0, 20));
+        values[5] = f64::NAN;
+        assert_eq!(detect(&values), Vec::new());
+    }
+}
@@ -0,0 +1,524 @@
+//! Condensed summaries of the comparison between two artifacts, suitable for
+//! posting to GitHub.
+
+use crate::api::{days, ServerResult};
+use crate::db::ArtifactId;
+use crate::load::InputData;
+use crate::selector::{self, SelfProfileData, Tag};
+use crate::server::handle_compare;
+use collector::Bound;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// The statistics included in summaries; the first one is used to decide on
+/// labels.
+pub const SUMMARY_STATS: &[&str] = &["instructions:u", "max-rss"];
+
+/// Changes smaller than this percentage are considered noise. This matches
+/// the highlighting threshold on the compare page.
+const SIGNIFICANCE_THRESHOLD: f64 = 1.0;
+
+/// How many test cases of the primary statistic have to regress for the
+/// summary to count as a regression. Single noisy test cases cross
+/// `SIGNIFICANCE_THRESHOLD` too often to comment on PRs for each of them.
+const MIN_REGRESSIONS: usize = 3;
+
+/// A single test case regressing by at least this percentage makes the
+/// summary a regression regardless of `MIN_REGRESSIONS`.
+const LARGE_REGRESSION: f64 = 5.0;
+
+/// How many of the largest changes `query_changes` looks into, as each needs
+/// the self-profile data of both artifacts to be loaded.
+const MAX_ATTRIBUTED_CHANGES: usize = 20;
+
+/// How many queries `query_changes` lists per change and criterion.
+const TOP_QUERIES: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    /// The benchmark and profile, e.g. `syn-opt`.
+    pub benchmark: String,
+    /// The cache state, e.g. `incr-patched: println`.
+    pub cache: String,
+    /// Relative change from the first to the second artifact, in percent.
+    pub percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatSummary {
+    pub stat: String,
+    /// Significant increases, largest first.
+    pub regressions: Vec<Change>,
+    /// Significant decreases, largest first.
+    pub improvements: Vec<Change>,
+    /// Benchmarks left out because their source changed between the artifacts.
+    pub source_changed: Vec<String>,
+}
+
+impl StatSummary {
+    /// Finds the significant changes from `a` to `b`, which map benchmarks
+    /// to values as in `DateData::data`.
+    pub fn new(
+        stat: &str,
+        a: &HashMap<String, Vec<(String, f64)>>,
+        b: &HashMap<String, Vec<(String, f64)>>,
+        source_changed: &[String],
+        annotations: &[database::Annotation],
+    ) -> StatSummary {
+        let mut regressions = Vec::new();
+        let mut improvements = Vec::new();
+        for (benchmark, a) in a {
+            let (krate, profile) = match benchmark.rfind('-') {
+                Some(idx) => (&benchmark[..idx], &benchmark[idx + 1..]),
+                None => (&benchmark[..], ""),
+            };
+            if source_changed.iter().any(|c| c == krate) {
+                continue;
+            }
+            let b = match b.get(benchmark) {
+                Some(b) => b,
+                None => continue,
+            };
+            for (cache, a) in a {
+                let b = match b.iter().find(|(c, _)| c == cache) {
+                    Some((_, b)) => *b,
+                    None => continue,
+                };
+                if *a == 0.0 {
+                    continue;
+                }
+                // Annotated changes are known, e.g. to be noise.
+                if annotations
+                    .iter()
+                    .any(|annotation| annotation.applies_to(krate, profile, cache))
+                {
+                    continue;
+                }
+                let change = Change {
+                    benchmark: benchmark.clone(),
+                    cache: cache.clone(),
+                    percent: 100.0 * (b - a) / a,
+                };
+                if change.percent >= SIGNIFICANCE_THRESHOLD {
+                    regressions.push(change);
+                } else if change.percent <= -SIGNIFICANCE_THRESHOLD {
+                    improvements.push(change);
+                }
+            }
+        }
+        regressions.sort_by(|a, b| b.percent.partial_cmp(&a.percent).unwrap());
+        improvements.sort_by(|a, b| a.percent.partial_cmp(&b.percent).unwrap());
+
+        StatSummary {
+            stat: stat.to_string(),
+            regressions,
+            improvements,
+            source_changed: source_changed.to_vec(),
+        }
+    }
+
+    /// The `n` largest changes in either direction, largest first.
+    pub fn top(&self, n: usize) -> Vec<&Change> {
+        let mut changes = self
+            .regressions
+            .iter()
+            .chain(self.improvements.iter())
+            .collect::<Vec<_>>();
+        changes.sort_by(|a, b| b.percent.abs().partial_cmp(&a.percent.abs()).unwrap());
+        changes.truncate(n);
+        changes
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub stats: Vec<StatSummary>,
+}
+
+impl Summary {
+    /// Compares the artifacts `a` and `b` (typically a commit and its parent)
+    /// for each of the `SUMMARY_STATS`.
+    pub async fn compute(data: &InputData, a: &str, b: &str) -> ServerResult<Summary> {
+        let response = handle_compare(
+            days::Request {
+                start: Bound::Commit(a.to_string()),
+                end: Bound::Commit(b.to_string()),
+                stat: SUMMARY_STATS[0].to_string(),
+                stats: SUMMARY_STATS[1..].iter().map(|s| s.to_string()).collect(),
+                self_profile: false,
+            },
+            data,
+        )
+        .await?;
+        Ok(Summary {
+            stats: response.summaries,
+        })
+    }
+
+    /// Whether the primary statistic regressed significantly: in several
+    /// test cases, or by a large amount in one.
+    pub fn is_regression(&self) -> bool {
+        self.stats.first().map_or(false, |s| {
+            s.regressions.len() >= MIN_REGRESSIONS
+                || s.regressions
+                    .first()
+                    .map_or(false, |c| c.percent >= LARGE_REGRESSION)
+        })
+    }
+
+    /// The label we suggest adding to the PR, if any.
+    pub fn label(&self) -> Option<&'static str> {
+        if self.is_regression() {
+            Some("perf-regression")
+        } else {
+            None
+        }
+    }
+
+    /// Renders the summary as markdown, listing up to `top` changes per
+    /// statistic.
+    pub fn to_markdown(&self, top: usize) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "| Statistic | Regressions | Improvements | Largest change |"
+        )
+        .unwrap();
+        writeln!(out, "|---|---:|---:|---:|").unwrap();
+        for stat in &self.stats {
+            let largest = stat
+                .top(1)
+                .first()
+                .map(|c| format!("{:+.1}%", c.percent))
+                .unwrap_or_else(|| String::from("-"));
+            writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                stat.stat,
+                stat.regressions.len(),
+                stat.improvements.len(),
+                largest
+            )
+            .unwrap();
+        }
+
+        for stat in &self.stats {
+            let changes = stat.top(top);
+            if changes.is_empty() {
+                continue;
+            }
+            writeln!(
+                out,
+                "\n<details><summary>Top {} changes in {}</summary>\n",
+                changes.len(),
+                stat.stat
+            )
+            .unwrap();
+            writeln!(out, "| Benchmark | Scenario | Change |").unwrap();
+            writeln!(out, "|---|---|---:|").unwrap();
+            for change in changes {
+                writeln!(
+                    out,
+                    "| {} | {} | {:+.1}% |",
+                    change.benchmark, change.cache, change.percent
+                )
+                .unwrap();
+            }
+            writeln!(out, "\n</details>").unwrap();
+        }
+
+        if let Some(stat) = self.stats.first() {
+            if !stat.source_changed.is_empty() {
+                writeln!(
+                    out,
+                    "\nNot compared because the benchmark changed: {}.",
+                    stat.source_changed.join(", ")
+                )
+                .unwrap();
+            }
+        }
+        if let Some(label) = self.label() {
+            writeln!(
+                out,
+                "\nThis looks like a regression in {}; consider adding the \
+                `{}` label.",
+                self.stats[0].stat, label
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+/// Finds the self-profile queries behind the largest changes of `summary`,
+/// from `a` to `b`. Changes without self-profile data for both artifacts are
+/// left out.
+pub async fn query_changes(
+    data: &InputData,
+    a: &ArtifactId,
+    b: &ArtifactId,
+    summary: &StatSummary,
+) -> ServerResult<Vec<days::QueryChanges>> {
+    let cids = Arc::new(vec![a.clone(), b.clone()]);
+    let mut result = Vec::new();
+    for change in summary.top(MAX_ATTRIBUTED_CHANGES) {
+        let (krate, profile) = match change.benchmark.rfind('-') {
+            Some(idx) => (&change.benchmark[..idx], &change.benchmark[idx + 1..]),
+            None => continue,
+        };
+        let query = selector::Query::new()
+            .set(Tag::Crate, selector::Selector::One(krate))
+            .set(Tag::Profile, selector::Selector::One(profile))
+            .set(Tag::Cache, selector::Selector::One(change.cache.as_str()));
+        let mut responses = data
+            .query::<Option<SelfProfileData>>(query, cids.clone())
+            .await?;
+        if responses.len() != 1 {
+            continue;
+        }
+        let mut series = responses.remove(0).series;
+        let (before, after) = match (series.next(), series.next()) {
+            (Some((_, Some(before))), Some((_, Some(after)))) => (before, after),
+            _ => continue,
+        };
+
+        let mut queries: HashMap<_, days::QueryChange> = HashMap::new();
+        for (idx, profile) in [before, after].iter().enumerate() {
+            for query in &profile.query_data {
+                let entry = queries
+                    .entry(query.label)
+                    .or_insert_with(|| days::QueryChange {
+                        label: query.label,
+                        self_time: (0.0, 0.0),
+                        invocation_count: (0, 0),
+                    });
+                let self_time = query.self_time().as_secs_f64();
+                if idx == 0 {
+                    entry.self_time.0 = self_time;
+                    entry.invocation_count.0 = query.invocation_count;
+                } else {
+                    entry.self_time.1 = self_time;
+                    entry.invocation_count.1 = query.invocation_count;
+                }
+            }
+        }
+        let queries = queries.into_iter().map(|(_, q)| q).collect::<Vec<_>>();
+
+        let mut by_self_time = queries
+            .iter()
+            .filter(|q| q.self_time.0 != q.self_time.1)
+            .cloned()
+            .collect::<Vec<_>>();
+        by_self_time.sort_by(|a, b| {
+            let a = (a.self_time.1 - a.self_time.0).abs();
+            let b = (b.self_time.1 - b.self_time.0).abs();
+            b.partial_cmp(&a).unwrap()
+        });
+        by_self_time.truncate(TOP_QUERIES);
+
+        let mut by_invocation_count = queries
+            .into_iter()
+            .filter(|q| q.invocation_count.0 != q.invocation_count.1)
+            .collect::<Vec<_>>();
+        by_invocation_count.sort_by_key(|q| {
+            let (a, b) = q.invocation_count;
+            std::cmp::Reverse((i64::from(b) - i64::from(a)).abs())
+        });
+        by_invocation_count.truncate(TOP_QUERIES);
+
+        result.push(days::QueryChanges {
+            benchmark: change.benchmark.clone(),
+            cache: change.cache.clone(),
+            percent: change.percent,
+            by_self_time,
+            by_invocation_count,
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Benchmark results, as in `DateData::data`.
+    fn results(values: &[(&str, &str, f64)]) -> HashMap<String, Vec<(String, f64)>> {
+        let mut results: HashMap<String, Vec<(String, f64)>> = HashMap::new();
+        for &(benchmark, cache, value) in values {
+            results
+                .entry(benchmark.to_string())
+                .or_default()
+                .push((cache.to_string(), value));
+        }
+        results
+    }
+
+    fn change(benchmark: &str, cache: &str, percent: f64) -> Change {
+        Change {
+            benchmark: benchmark.to_string(),
+            cache: cache.to_string(),
+            percent,
+        }
+    }
+
+    fn summary(regressions: &[f64], improvements: &[f64]) -> Summary {
+        let changes = |name: &str, percents: &[f64]| {
+            percents
+                .iter()
+                .enumerate()
+                .map(|(i, &p)| change(&format!("{}{}-opt", name, i), "full", p))
+                .collect()
+        };
+        Summary {
+            stats: vec![StatSummary {
+                stat: String::from("instructions:u"),
+                regressions: changes("regressed", regressions),
+                improvements: changes("improved", improvements),
+                source_changed: Vec::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn classifies_changes() {
+        let a = results(&[
+            ("syn-opt", "full", 100.0),
+            ("syn-opt", "incr-full", 100.0),
+            ("serde-debug", "full", 200.0),
+            ("regex-check", "full", 100.0),
+            ("gone-opt", "full", 100.0),
+        ]);
+        let b = results(&[
+            ("syn-opt", "full", 103.0),
+            ("syn-opt", "incr-full", 100.5),
+            ("serde-debug", "full", 180.0),
+            ("regex-check", "full", 110.0),
+        ]);
+        let summary = StatSummary::new("instructions:u", &a, &b, &[], &[]);
+        // Changes below the threshold are noise; results missing from
+        // either side are ignored.
+        assert_eq!(
+            summary.regressions,
+            vec![
+                change("regex-check", "full", 10.0),
+                change("syn-opt", "full", 3.0),
+            ]
+        );
+        assert_eq!(
+            summary.improvements,
+            vec![change("serde-debug", "full", -10.0)]
+        );
+        assert_eq!(
+            summary
+                .top(2)
+                .iter()
+                .map(|c| c.benchmark.as_str())
+                .collect::<Vec<_>>(),
+            vec!["regex-check", "serde-debug"]
+        );
+    }
+
+    #[test]
+    fn skips_changed_and_annotated_benchmarks() {
+        let a = results(&[
+            ("syn-opt", "full", 100.0),
+            ("syn-debug", "full", 100.0),
+            ("regex-opt", "full", 100.0),
+            ("regex-opt", "incr-full", 100.0),
+        ]);
+        let b = results(&[
+            ("syn-opt", "full", 150.0),
+            ("syn-debug", "full", 150.0),
+            ("regex-opt", "full", 150.0),
+            ("regex-opt", "incr-full", 150.0),
+        ]);
+        let annotation = database::Annotation {
+            id: 1,
+            start: String::from("a"),
+            end: String::from("b"),
+            benchmark: Some(String::from("regex")),
+            profile: Some(String::from("opt")),
+            cache: Some(String::from("full")),
+            author: String::from("someone"),
+            text: String::from("noise"),
+            created: database::Date::empty(),
+        };
+        let summary = StatSummary::new(
+            "instructions:u",
+            &a,
+            &b,
+            &[String::from("syn")],
+            &[annotation],
+        );
+        assert_eq!(
+            summary.regressions,
+            vec![change("regex-opt", "incr-full", 50.0)]
+        );
+        assert!(summary.improvements.is_empty());
+        assert_eq!(summary.source_changed, vec![String::from("syn")]);
+    }
+
+    #[test]
+    fn regressions() {
+        assert!(!summary(&[], &[]).is_regression());
+        // A couple of small regressions may well be noise.
+        assert!(!summary(&[2.0, 1.5], &[]).is_regression());
+        assert!(summary(&[2.0, 1.5, 1.0], &[]).is_regression());
+        // A single large one is.
+        assert!(summary(&[LARGE_REGRESSION], &[]).is_regression());
+        assert!(!summary(&[], &[-10.0, -10.0, -10.0]).is_regression());
+        assert_eq!(summary(&[], &[]).label(), None);
+        assert_eq!(
+            summary(&[2.0, 1.5, 1.0], &[]).label(),
+            Some("perf-regression")
+        );
+    }
+
+    #[test]
+    fn only_the_primary_stat_decides() {
+        let mut summary = summary(&[], &[]);
+        summary.stats.push(StatSummary {
+            stat: String::from("max-rss"),
+            regressions: vec![change("syn-opt", "full", 20.0)],
+            improvements: Vec::new(),
+            source_changed: Vec::new(),
+        });
+        assert!(!summary.is_regression());
+    }
+
+    #[test]
+    fn markdown() {
+        let mut summary = summary(&[2.0, 1.5, 1.0], &[-4.0]);
+        summary.stats[0].source_changed = vec![String::from("syn")];
+        summary.stats.push(StatSummary {
+            stat: String::from("max-rss"),
+            regressions: Vec::new(),
+            improvements: Vec::new(),
+            source_changed: Vec::new(),
+        });
+        assert_eq!(
+            summary.to_markdown(2),
+            "\
+| Statistic | Regressions | Improvements | Largest change |
+|---|---:|---:|---:|
+| instructions:u | 3 | 1 | -4.0% |
+| max-rss | 0 | 0 | - |
+
+<details><summary>Top 2 changes in instructions:u</summary>
+
+| Benchmark | Scenario | Change |
+|---|---|---:|
+| improved0-opt | full | -4.0% |
+| regressed0-opt | full | +2.0% |
+
+</details>
+
+Not compared because the benchmark changed: syn.
+
+This looks like a regression in instructions:u; consider adding the `perf-regression` label.
+"
+        );
+    }
+}
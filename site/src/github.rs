@@ -1,40 +1,41 @@
+pub mod client;
+
 use crate::api::{github, ServerResult};
-use crate::load::{Config, InputData, TryCommit};
+use crate::comparison::{Summary, SUMMARY_STATS};
+use crate::load::{InputData, MissingReason, TryCommit};
 use anyhow::Context as _;
+use collector::api::next_commit;
 use hashbrown::HashSet;
 use serde::Deserialize;
 
 use database::ArtifactId;
 use regex::Regex;
-use reqwest::header::USER_AGENT;
+use std::collections::BTreeMap;
 use std::{sync::Arc, time::Duration};
 
 lazy_static::lazy_static! {
     static ref BODY_TRY_COMMIT: Regex =
-        Regex::new(r#"(?:\W|^)@rust-timer\s+build\s+(\w+)(?:\W|$)(?:include=(\S+))?\s*(?:exclude=(\S+))?\s*(?:runs=(\d+))?"#).unwrap();
+        Regex::new(r#"(?:\W|^)@rust-timer\s+build\s+(\w+)(?:\W|$)(?:include=(\S+))?\s*(?:exclude=(\S+))?\s*(?:runs=(\d+))?\s*(?:builds=(\S+))?\s*(?:scenarios=(\S+))?\s*(?:priority=(high))?"#).unwrap();
     static ref BODY_QUEUE: Regex =
-        Regex::new(r#"(?:\W|^)@rust-timer\s+queue(?:\W|$)(?:include=(\S+))?\s*(?:exclude=(\S+))?\s*(?:runs=(\d+))?"#).unwrap();
+        Regex::new(r#"(?:\W|^)@rust-timer\s+queue(?:\W|$)(?:include=(\S+))?\s*(?:exclude=(\S+))?\s*(?:runs=(\d+))?\s*(?:builds=(\S+))?\s*(?:scenarios=(\S+))?\s*(?:priority=(high))?"#).unwrap();
+    static ref BODY_CANCEL: Regex =
+        Regex::new(r#"(?:\W|^)@rust-timer\s+cancel(?:\W|$)"#).unwrap();
+    static ref BODY_STATUS: Regex =
+        Regex::new(r#"(?:\W|^)@rust-timer\s+status(?:\W|$)"#).unwrap();
     static ref BODY_MAKE_PR_FOR: Regex =
         Regex::new(r#"(?:\W|^)@rust-timer\s+make-pr-for\s+(\w+)(?:\W|$)"#).unwrap();
     static ref BODY_UDPATE_PR_FOR: Regex =
         Regex::new(r#"(?:\W|^)@rust-timer\s+update-branch-for\s+(\w+)(?:\W|$)"#).unwrap();
+    static ref BODY_UNROLL: Regex =
+        Regex::new(r#"(?:\W|^)@rust-timer\s+unroll\s+(\w+)(?:\W|$)"#).unwrap();
 }
 
-async fn get_authorized_users() -> ServerResult<Vec<usize>> {
-    let url = format!("{}/permissions/perf.json", ::rust_team_data::v1::BASE_URL);
-    let client = reqwest::Client::new();
-    client
-        .get(&url)
-        .send()
-        .await
-        .map_err(|err| format!("failed to fetch authorized users: {}", err))?
-        .error_for_status()
-        .map_err(|err| format!("failed to fetch authorized users: {}", err))?
-        .json::<rust_team_data::v1::Permission>()
-        .await
-        .map_err(|err| format!("failed to fetch authorized users: {}", err))
-        .map(|perms| perms.github_ids)
-}
+/// How many of the largest changes per statistic are listed in comments.
+const SUMMARY_TOP_CHANGES: usize = 5;
+
+/// How many of the most recently benchmarked master commits are checked for
+/// regressions against their parent.
+const REGRESSION_CHECK_WINDOW: usize = 10;
 
 pub async fn handle_github(
     request: github::Request,
@@ -51,12 +52,14 @@ pub async fn handle_github(
     }
 
     if request.comment.author_association != github::Association::Owner
-        && !get_authorized_users()
+        && !data
+            .github
+            .authorized_users()
             .await?
             .contains(&request.comment.user.id)
     {
         post_comment(
-            &data.config,
+            &data,
             request.issue.number,
             "Insufficient permissions to issue commands to rust-timer.",
         )
@@ -64,17 +67,44 @@ pub async fn handle_github(
         return Ok(github::Response);
     }
 
+    if BODY_CANCEL.is_match(&request.comment.body) {
+        let msg = cancel_queued(&data, request.issue.number).await;
+        post_comment(&data, request.issue.number, msg).await;
+        return Ok(github::Response);
+    }
+
+    if BODY_STATUS.is_match(&request.comment.body) {
+        let msg = queue_status(&data, request.issue.number).await;
+        post_comment(&data, request.issue.number, msg).await;
+        return Ok(github::Response);
+    }
+
     if let Some(captures) = BODY_QUEUE.captures(&request.comment.body) {
         let include = captures.get(1).map(|v| v.as_str());
         let exclude = captures.get(2).map(|v| v.as_str());
         let runs = captures.get(3).and_then(|v| v.as_str().parse::<i32>().ok());
+        let builds = captures.get(4).map(|v| v.as_str());
+        let scenarios = captures.get(5).map(|v| v.as_str());
+        let high_priority = captures.get(6).is_some();
+        if let Err(msg) = check_selection(builds, scenarios) {
+            post_comment(&data, request.issue.number, msg).await;
+            return Ok(github::Response);
+        }
         {
             let conn = data.conn().await;
-            conn.queue_pr(request.issue.number, include, exclude, runs)
-                .await;
+            conn.queue_pr(
+                request.issue.number,
+                include,
+                exclude,
+                runs,
+                builds,
+                scenarios,
+                high_priority,
+            )
+            .await;
         }
         post_comment(
-            &data.config,
+            &data,
             request.issue.number,
             "Awaiting bors try build completion.
 
@@ -89,17 +119,63 @@ pub async fn handle_github(
             let include = captures.get(2).map(|v| v.as_str());
             let exclude = captures.get(3).map(|v| v.as_str());
             let runs = captures.get(4).and_then(|v| v.as_str().parse::<i32>().ok());
+            let builds = captures.get(5).map(|v| v.as_str());
+            let scenarios = captures.get(6).map(|v| v.as_str());
+            let high_priority = captures.get(7).is_some();
+            if let Err(msg) = check_selection(builds, scenarios) {
+                post_comment(&data, request.issue.number, msg).await;
+                return Ok(github::Response);
+            }
             let commit = commit.trim_start_matches("https://github.com/rust-lang/rust/commit/");
             {
                 let conn = data.conn().await;
-                conn.queue_pr(request.issue.number, include, exclude, runs)
-                    .await;
+                conn.queue_pr(
+                    request.issue.number,
+                    include,
+                    exclude,
+                    runs,
+                    builds,
+                    scenarios,
+                    high_priority,
+                )
+                .await;
             }
             let f = enqueue_sha(request, &data, commit.to_owned());
             return f.await;
         }
     }
 
+    if let Some(captures) = BODY_UNROLL.captures(&request.comment.body) {
+        if let Some(rollup_merge) = captures.get(1).map(|c| c.as_str().to_owned()) {
+            let rollup_merge =
+                rollup_merge.trim_start_matches("https://github.com/rust-lang/rust/commit/");
+            let prs = unroll_rollup(
+                data.clone(),
+                &request.issue.repository_url,
+                request.issue.number,
+                &rollup_merge,
+                &request.comment.html_url,
+            )
+            .await
+            .map_err(|e| format!("{:?}", e))?;
+            let prs = prs
+                .iter()
+                .map(|(rolled_up, perf)| format!("#{} (as #{})", rolled_up, perf))
+                .collect::<Vec<_>>();
+            post_comment(
+                &data,
+                request.issue.number,
+                format!(
+                    "Created perf-test PRs for {}. A summary will be posted here once \
+                    all of them have been benchmarked.",
+                    prs.join(", ")
+                ),
+            )
+            .await;
+            return Ok(github::Response);
+        }
+    }
+
     let captures = BODY_MAKE_PR_FOR
         .captures_iter(&request.comment.body)
         .collect::<Vec<_>>();
@@ -107,9 +183,7 @@ pub async fn handle_github(
         if let Some(rollup_merge) = capture.get(1).map(|c| c.as_str().to_owned()) {
             let rollup_merge =
                 rollup_merge.trim_start_matches("https://github.com/rust-lang/rust/commit/");
-            let client = reqwest::Client::new();
             pr_and_try_for_rollup(
-                &client,
                 data.clone(),
                 &request.issue.repository_url,
                 &rollup_merge,
@@ -131,13 +205,11 @@ pub async fn handle_github(
             // This just creates or updates the branch for this merge commit.
             // Intended for resolving the race condition of master merging in
             // between us updating the commit and merging things.
-            let client = reqwest::Client::new();
-            let branch =
-                branch_for_rollup(&client, &data, &request.issue.repository_url, rollup_merge)
-                    .await
-                    .map_err(|e| e.to_string())?;
+            let branch = branch_for_rollup(&data, &request.issue.repository_url, rollup_merge)
+                .await
+                .map_err(|e| e.to_string())?;
             post_comment(
-                &data.config,
+                &data,
                 request.issue.number,
                 &format!("Master base SHA: {}", branch.master_base_sha),
             )
@@ -148,9 +220,161 @@ pub async fn handle_github(
     Ok(github::Response)
 }
 
+/// Checks the `builds=` and `scenarios=` arguments, so that the collector is
+/// never handed a selection it cannot run. Returns the reply to post if they
+/// are invalid.
+fn check_selection(builds: Option<&str>, scenarios: Option<&str>) -> Result<(), String> {
+    let lists = [
+        ("build kind", builds, next_commit::BUILD_KINDS),
+        ("scenario", scenarios, next_commit::SCENARIOS),
+    ];
+    for &(what, list, known) in &lists {
+        for name in list.into_iter().flat_map(|l| l.split(',')) {
+            if !known.contains(&name) {
+                return Err(format!(
+                    "`{}` is not a known {}; expected a comma-separated list of {}.",
+                    name,
+                    what,
+                    known.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the PR's queued try build, unless it is already being benchmarked.
+/// Returns the reply to post.
+async fn cancel_queued(data: &InputData, pr: u32) -> String {
+    let conn = data.conn().await;
+    if let Some(queued) = conn.queued_commits().await.into_iter().find(|c| c.pr == pr) {
+        let in_progress = conn
+            .in_progress_artifacts()
+            .await
+            .into_iter()
+            .any(|aid| match aid {
+                ArtifactId::Commit(c) => c.sha == queued.sha,
+                ArtifactId::Artifact(_) => false,
+            });
+        if in_progress {
+            return format!(
+                "{} is already being benchmarked and can no longer be cancelled.",
+                queued.sha
+            );
+        }
+    }
+
+    if conn.cancel_pr(pr).await {
+        String::from(
+            "Cancelled the queued perf run.
+
+@rustbot label: -S-waiting-on-perf",
+        )
+    } else {
+        String::from("There is no queued perf run to cancel.")
+    }
+}
+
+/// Describes where the PR's try build is in the benchmark queue, with an
+/// estimate of when its results will be available based on the durations
+/// of the steps of the builds currently being benchmarked, one per collector.
+async fn queue_status(data: &InputData, pr: u32) -> String {
+    let missing = data.missing_commits().await;
+    let position = missing.iter().position(|(_, reason)| match reason {
+        MissingReason::Try { pr: p, .. } => *p == pr,
+        MissingReason::InProgress(Some(previous)) => match &**previous {
+            MissingReason::Try { pr: p, .. } => *p == pr,
+            _ => false,
+        },
+        _ => false,
+    });
+    let position = match position {
+        Some(position) => position,
+        None => {
+            return String::from(
+                "No try build of this PR is waiting to be benchmarked. If one was \
+                requested it will be queued once bors reports the try build as \
+                successful.",
+            )
+        }
+    };
+
+    // (artifact, duration of a full run, time until it finishes)
+    let conn = data.conn().await;
+    let mut in_progress = Vec::new();
+    for artifact in conn.in_progress_artifacts().await {
+        let steps = conn.in_progress_steps(&artifact).await;
+        let per_run: Duration = steps.iter().map(|s| s.expected).sum();
+        let remaining: Duration = steps
+            .iter()
+            .filter(|s| !s.is_done)
+            .map(|s| s.expected.checked_sub(s.duration).unwrap_or_default())
+            .sum();
+        if per_run != Duration::default() {
+            in_progress.push((artifact, per_run, remaining));
+        }
+    }
+
+    let (aid, reason) = &missing[position];
+    if let MissingReason::InProgress(_) = reason {
+        return match in_progress.iter().find(|(artifact, ..)| artifact == aid) {
+            Some((_, _, remaining)) => format!(
+                "{} is currently being benchmarked; it should finish in about {}.",
+                aid.name(),
+                format_duration(*remaining)
+            ),
+            None => format!("{} is currently being benchmarked.", aid.name()),
+        };
+    }
+
+    // Every queued artifact up to and including this one needs a full run,
+    // and the collectors take them in turn as they finish their current one.
+    let runs = missing[..=position]
+        .iter()
+        .filter(|(_, reason)| match reason {
+            MissingReason::InProgress(_) => false,
+            _ => true,
+        })
+        .count() as u32;
+    let mut msg = format!(
+        "{} is number {} of {} in the queue.",
+        aid.name(),
+        position + 1,
+        missing.len()
+    );
+    if !in_progress.is_empty() {
+        let collectors = in_progress.len() as u32;
+        let per_run = in_progress
+            .iter()
+            .map(|(_, per_run, _)| *per_run)
+            .sum::<Duration>()
+            / collectors;
+        let first_done = in_progress
+            .iter()
+            .map(|(_, _, remaining)| *remaining)
+            .min()
+            .unwrap();
+        let rounds = (runs + collectors - 1) / collectors;
+        msg.push_str(&format!(
+            " Results are expected in about {}.",
+            format_duration(first_done + per_run * rounds)
+        ));
+    }
+    msg
+}
+
+/// Formats a duration with minute precision, e.g. `2h 05m`.
+fn format_duration(duration: Duration) -> String {
+    let minutes = (duration.as_secs() + 59) / 60;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
+
 // Returns the PR number
 async fn pr_and_try_for_rollup(
-    client: &reqwest::Client,
     data: Arc<InputData>,
     repository_url: &str,
     rollup_merge_sha: &str,
@@ -161,28 +385,28 @@ async fn pr_and_try_for_rollup(
         repository_url,
         rollup_merge_sha
     );
-    let branch = branch_for_rollup(client, &data, repository_url, rollup_merge_sha).await?;
+    let branch = branch_for_rollup(&data, repository_url, rollup_merge_sha).await?;
 
-    let pr = create_pr(
-        client,
-        &data,
-        repository_url,
-        &format!(
-            "[DO NOT MERGE] perf-test for #{}",
-            branch.rolled_up_pr_number
-        ),
-        &format!("rust-timer:{}", branch.name),
-        "master",
-        &format!(
-            "This is an automatically generated pull request (from [here]({})) to \
+    let pr = data
+        .github
+        .create_pr(
+            repository_url,
+            &format!(
+                "[DO NOT MERGE] perf-test for #{}",
+                branch.rolled_up_pr_number
+            ),
+            &format!("rust-timer:{}", branch.name),
+            "master",
+            &format!(
+                "This is an automatically generated pull request (from [here]({})) to \
             run perf tests for #{} which merged in a rollup.
 
 r? @ghost",
-            origin_url, branch.rolled_up_pr_number
-        ),
-    )
-    .await
-    .context("Created PR")?;
+                origin_url, branch.rolled_up_pr_number
+            ),
+        )
+        .await
+        .context("Created PR")?;
 
     let pr_number = pr.number;
     let rollup_merge_sha = rollup_merge_sha.to_owned();
@@ -198,7 +422,7 @@ r? @ghost",
         // off: we'd need to store the state in the database and handle the try
         // build starting and generally that's a lot of work for not too much gain.
         post_comment(
-            &data.config,
+            &data,
             pr.number,
             &format!(
                 "@bors try @rust-timer queue
@@ -222,6 +446,80 @@ build hasn't yet started.",
     Ok(pr_number)
 }
 
+/// Creates a perf-test PR, and queues its try build, for each PR merged by the
+/// rollup whose bors merge commit is `merge_sha`. Returns the rolled up PR and
+/// the corresponding perf-test PR, in the order they were merged.
+async fn unroll_rollup(
+    data: Arc<InputData>,
+    repository_url: &str,
+    rollup_pr: u32,
+    merge_sha: &str,
+    origin_url: &str,
+) -> anyhow::Result<Vec<(u32, u32)>> {
+    let merge = data
+        .github
+        .get_commit(repository_url, merge_sha)
+        .await
+        .context("got rollup merge")?;
+    if merge.parents.len() != 2 {
+        anyhow::bail!("{} is not a merge commit", merge_sha);
+    }
+
+    let mut rollup_merges = Vec::new();
+    let mut current = data
+        .github
+        .get_commit(repository_url, &merge.parents[1].sha)
+        .await
+        .context("got rollup head")?;
+    while let Some(pr) = parse_rolled_up_pr(&current.commit.message) {
+        rollup_merges.push((pr, current.sha.clone()));
+        current = data
+            .github
+            .get_commit(repository_url, &current.parents[0].sha)
+            .await
+            .context("got rollup merge parent")?;
+    }
+    rollup_merges.reverse();
+    if rollup_merges.is_empty() {
+        anyhow::bail!("{} does not merge a rollup", merge_sha);
+    }
+
+    let mut prs = Vec::new();
+    for (rolled_up_pr, sha) in rollup_merges {
+        let perf_pr = pr_and_try_for_rollup(data.clone(), repository_url, &sha, origin_url).await?;
+        prs.push((rolled_up_pr, perf_pr));
+    }
+
+    // Recorded only once every perf-test PR exists, as the summary waits for
+    // all recorded members and would never be posted for a partial rollup.
+    let mut conn = data.conn().await;
+    let mut tx = conn.transaction().await;
+    for (position, &(rolled_up_pr, perf_pr)) in prs.iter().enumerate() {
+        tx.conn()
+            .record_rollup_member(
+                rollup_pr,
+                position as u32,
+                rolled_up_pr,
+                perf_pr,
+                &merge.parents[0].sha,
+            )
+            .await;
+    }
+    tx.commit().await.context("recorded rollup members")?;
+
+    Ok(prs)
+}
+
+/// Parses the PR number out of a `Rollup merge of #N - ...` commit message.
+fn parse_rolled_up_pr(message: &str) -> Option<u32> {
+    message
+        .strip_prefix("Rollup merge of #")?
+        .split_whitespace()
+        .next()?
+        .parse::<u32>()
+        .ok()
+}
+
 struct RollupBranch {
     master_base_sha: String,
     rolled_up_pr_number: u32,
@@ -229,12 +527,13 @@ struct RollupBranch {
 }
 
 async fn branch_for_rollup(
-    client: &reqwest::Client,
     data: &InputData,
     repository_url: &str,
     rollup_merge_sha: &str,
 ) -> anyhow::Result<RollupBranch> {
-    let rollup_merge = get_commit(&client, &data, repository_url, rollup_merge_sha)
+    let rollup_merge = data
+        .github
+        .get_commit(repository_url, rollup_merge_sha)
         .await
         .context("got rollup merge")?;
 
@@ -245,52 +544,50 @@ async fn branch_for_rollup(
             break;
         }
         assert_eq!(current.parents.len(), 2);
-        current = get_commit(&client, &data, repository_url, &current.parents[0].sha)
+        current = data
+            .github
+            .get_commit(repository_url, &current.parents[0].sha)
             .await
             .context("success master get")?;
     }
     let old_master_commit = current;
 
-    let current_master_commit = get_commit(&client, &data, repository_url, "master")
+    let current_master_commit = data
+        .github
+        .get_commit(repository_url, "master")
         .await
         .context("success master get")?;
 
-    let revert_sha = create_commit(
-        &client,
-        &data,
-        "https://api.github.com/repos/rust-timer/rust",
-        &format!("Revert to {}", old_master_commit.sha),
-        &old_master_commit.commit.tree.sha,
-        &[&current_master_commit.sha],
-    )
-    .await
-    .context("create revert")?;
-
-    let merge_sha = create_commit(
-        &client,
-        &data,
-        "https://api.github.com/repos/rust-timer/rust",
-        &format!(
-            "rust-timer simulated merge of {}\n\nOriginal message:\n{}",
-            rollup_merge.sha, rollup_merge.commit.message
-        ),
-        &rollup_merge.commit.tree.sha,
-        &[&revert_sha],
-    )
-    .await
-    .context("create merge commit")?;
-
-    let rolled_up_pr_number = if let Some(stripped) = rollup_merge
-        .commit
-        .message
-        .strip_prefix("Rollup merge of #")
-    {
-        stripped
-            .split_whitespace()
-            .next()
-            .unwrap()
-            .parse::<u32>()
-            .unwrap()
+    // The simulated merges are pushed to rust-timer's fork.
+    let rust_timer_repo = format!("{}/repos/rust-timer/rust", data.github.api_url());
+
+    let revert_sha = data
+        .github
+        .create_commit(
+            &rust_timer_repo,
+            &format!("Revert to {}", old_master_commit.sha),
+            &old_master_commit.commit.tree.sha,
+            &[&current_master_commit.sha],
+        )
+        .await
+        .context("create revert")?;
+
+    let merge_sha = data
+        .github
+        .create_commit(
+            &rust_timer_repo,
+            &format!(
+                "rust-timer simulated merge of {}\n\nOriginal message:\n{}",
+                rollup_merge.sha, rollup_merge.commit.message
+            ),
+            &rollup_merge.commit.tree.sha,
+            &[&revert_sha],
+        )
+        .await
+        .context("create merge commit")?;
+
+    let rolled_up_pr_number = if let Some(pr) = parse_rolled_up_pr(&rollup_merge.commit.message) {
+        pr
     } else {
         anyhow::bail!(
             "not a rollup merge commit: {:?}",
@@ -299,15 +596,14 @@ async fn branch_for_rollup(
     };
 
     let branch = format!("try-for-{}", rolled_up_pr_number);
-    create_ref(
-        &client,
-        &data,
-        "https://api.github.com/repos/rust-timer/rust",
-        &format!("refs/heads/{}", branch),
-        &merge_sha,
-    )
-    .await
-    .context("created branch")?;
+    data.github
+        .create_ref(
+            &rust_timer_repo,
+            &format!("refs/heads/{}", branch),
+            &merge_sha,
+        )
+        .await
+        .context("created branch")?;
 
     Ok(RollupBranch {
         rolled_up_pr_number,
@@ -316,191 +612,14 @@ async fn branch_for_rollup(
     })
 }
 
-#[derive(serde::Serialize)]
-struct CreateRefRequest<'a> {
-    // Must start with `refs/` and have at least two slashes.
-    // e.g. `refs/heads/master`.
-    #[serde(rename = "ref")]
-    ref_: &'a str,
-    sha: &'a str,
-}
-
-pub async fn create_ref(
-    client: &reqwest::Client,
-    data: &InputData,
-    repository_url: &str,
-    ref_: &str,
-    sha: &str,
-) -> anyhow::Result<()> {
-    let timer_token = data
-        .config
-        .keys
-        .github
-        .clone()
-        .expect("needs rust-timer token");
-    let url = format!("{}/git/refs", repository_url);
-    let response = client
-        .post(&url)
-        .json(&CreateRefRequest { ref_, sha })
-        .header(USER_AGENT, "perf-rust-lang-org-server")
-        .basic_auth("rust-timer", Some(timer_token))
-        .send()
-        .await
-        .context("POST git/refs failed")?;
-    if response.status() != reqwest::StatusCode::CREATED {
-        anyhow::bail!("{:?} != 201 CREATED", response.status());
-    }
-
-    Ok(())
-}
-
-#[derive(serde::Serialize)]
-struct CreatePrRequest<'a> {
-    title: &'a str,
-    // username:branch if cross-repo
-    head: &'a str,
-    // branch to pull into (e.g, master)
-    base: &'a str,
-    #[serde(rename = "body")]
-    description: &'a str,
-}
-
-#[derive(Debug, serde::Deserialize)]
-pub struct CreatePrResponse {
-    pub number: u32,
-    pub html_url: String,
-    pub comments_url: String,
-}
-
-pub async fn create_pr(
-    client: &reqwest::Client,
-    data: &InputData,
-    repository_url: &str,
-    title: &str,
-    head: &str,
-    base: &str,
-    description: &str,
-) -> anyhow::Result<CreatePrResponse> {
-    let timer_token = data
-        .config
-        .keys
-        .github
-        .clone()
-        .expect("needs rust-timer token");
-    let url = format!("{}/pulls", repository_url);
-    let response = client
-        .post(&url)
-        .json(&CreatePrRequest {
-            title,
-            head,
-            base,
-            description,
-        })
-        .header(USER_AGENT, "perf-rust-lang-org-server")
-        .basic_auth("rust-timer", Some(timer_token))
-        .send()
-        .await
-        .context("POST pulls failed")?;
-    if response.status() != reqwest::StatusCode::CREATED {
-        anyhow::bail!("{:?} != 201 CREATED", response.status());
-    }
-
-    Ok(response.json().await.context("deserializing failed")?)
-}
-
-#[derive(serde::Serialize)]
-struct CreateCommitRequest<'a> {
-    message: &'a str,
-    tree: &'a str,
-    parents: &'a [&'a str],
-}
-
-#[derive(serde::Deserialize)]
-struct CreateCommitResponse {
-    sha: String,
-}
-
-pub async fn create_commit(
-    client: &reqwest::Client,
-    data: &InputData,
-    repository_url: &str,
-    message: &str,
-    tree: &str,
-    parents: &[&str],
-) -> anyhow::Result<String> {
-    let timer_token = data
-        .config
-        .keys
-        .github
-        .clone()
-        .expect("needs rust-timer token");
-    let url = format!("{}/git/commits", repository_url);
-    let commit_response = client
-        .post(&url)
-        .json(&CreateCommitRequest {
-            message,
-            tree,
-            parents,
-        })
-        .header(USER_AGENT, "perf-rust-lang-org-server")
-        .basic_auth("rust-timer", Some(timer_token))
-        .send()
-        .await
-        .context("POST git/commits failed")?;
-    if commit_response.status() != reqwest::StatusCode::CREATED {
-        anyhow::bail!("{:?} != 201 CREATED", commit_response.status());
-    }
-
-    Ok(commit_response
-        .json::<CreateCommitResponse>()
-        .await
-        .context("deserializing failed")?
-        .sha)
-}
-
-pub async fn get_commit(
-    client: &reqwest::Client,
-    data: &InputData,
-    repository_url: &str,
-    sha: &str,
-) -> anyhow::Result<github::Commit> {
-    let timer_token = data
-        .config
-        .keys
-        .github
-        .clone()
-        .expect("needs rust-timer token");
-    let url = format!("{}/commits/{}", repository_url, sha);
-    let commit_response = client
-        .get(&url)
-        .header(USER_AGENT, "perf-rust-lang-org-server")
-        .basic_auth("rust-timer", Some(timer_token))
-        .send()
-        .await
-        .context("cannot get commit")?;
-    let commit_response = match commit_response.text().await {
-        Ok(c) => c,
-        Err(err) => {
-            anyhow::bail!("Failed to decode response for {}: {:?}", url, err);
-        }
-    };
-    match serde_json::from_str(&commit_response) {
-        Ok(c) => Ok(c),
-        Err(e) => Err(anyhow::anyhow!(
-            "cannot deserialize commit ({}): {:?}",
-            commit_response,
-            e
-        )),
-    }
-}
-
 async fn enqueue_sha(
     request: github::Request,
     data: &InputData,
     commit: String,
 ) -> ServerResult<github::Response> {
-    let client = reqwest::Client::new();
-    let commit_response = get_commit(&client, data, &request.issue.repository_url, &commit)
+    let commit_response = data
+        .github
+        .get_commit(&request.issue.repository_url, &commit)
         .await
         .map_err(|e| e.to_string())?;
     if commit_response.parents.len() != 2 {
@@ -532,7 +651,7 @@ async fn enqueue_sha(
             commit_response.parents[0].sha,
             try_commit.comparison_url(),
         );
-        post_comment(&data.config, request.issue.number, msg).await;
+        post_comment(&data, request.issue.number, msg).await;
     }
     Ok(github::Response)
 }
@@ -568,27 +687,11 @@ async fn handle_homu_res(request: &github::Request) -> Option<String> {
     Some(sha)
 }
 
-pub async fn post_comment<B>(cfg: &Config, pr: u32, body: B)
+pub async fn post_comment<B>(data: &InputData, pr: u32, body: B)
 where
     B: Into<String>,
 {
-    let body = body.into();
-    let timer_token = cfg.keys.github.clone().expect("needs rust-timer token");
-    let client = reqwest::Client::new();
-    let req = client
-        .post(&format!(
-            "https://api.github.com/repos/rust-lang/rust/issues/{}/comments",
-            pr
-        ))
-        .json(&github::PostComment {
-            body: body.to_owned(),
-        })
-        .header(USER_AGENT, "perf-rust-lang-org-server")
-        .basic_auth("rust-timer", Some(timer_token));
-
-    if let Err(e) = req.send().await {
-        eprintln!("failed to post comment: {:?}", e);
-    }
+    data.github.post_comment(pr, body.into()).await;
 }
 
 pub async fn post_finished(data: &InputData) {
@@ -612,8 +715,7 @@ pub async fn post_finished(data: &InputData) {
                 commits.remove(&c.sha);
             }
             ArtifactId::Artifact(_) => {
-                // do nothing, for now, though eventually we'll want an artifact
-                // queue
+                // queued artifacts are completed by `InputData::complete_artifacts`
             }
         }
     }
@@ -631,12 +733,21 @@ pub async fn post_finished(data: &InputData) {
                 "https://perf.rust-lang.org/compare.html?start={}&end={}",
                 commit.parent_sha, commit.sha
             );
+            let summary = match Summary::compute(data, &commit.parent_sha, &commit.sha).await {
+                Ok(summary) => summary.to_markdown(SUMMARY_TOP_CHANGES),
+                Err(e) => {
+                    log::error!("failed to summarize {}: {}", commit.sha, e);
+                    String::from("Summary unavailable; see the comparison url for details.")
+                }
+            };
             post_comment(
-                &data.config,
+                &data,
                 commit.pr,
                 format!(
                     "Finished benchmarking try commit ({}): [comparison url]({}).
 
+{}
+
 Benchmarking this pull request likely means that it is \
 perf-sensitive, so we're automatically marking it as not fit \
 for rolling up. Please note that if the perf results are \
@@ -649,10 +760,166 @@ regressions or improvements in the roll up.
 
 @bors rollup=never
 @rustbot label: +S-waiting-on-review -S-waiting-on-perf",
-                    commit.sha, comparison_url
+                    commit.sha, comparison_url, summary
                 ),
             )
             .await;
         }
     }
 }
+
+/// Compares recently benchmarked master commits against their parents, and
+/// notifies the PR which landed the commit of any significant regression.
+pub async fn post_regressions(data: &InputData) {
+    // As with `post_finished`, avoid recording checks we can't report.
+    if data.config.keys.github.is_none() {
+        return;
+    }
+    let master_commits = data.master_commits().await;
+    let conn = data.conn().await;
+    let index = data.index.load();
+    let benchmarked = index
+        .commits()
+        .into_iter()
+        .filter(|c| !c.is_try())
+        .collect::<Vec<_>>();
+    let in_progress = conn.in_progress_artifacts().await;
+
+    for commit in benchmarked.iter().rev().take(REGRESSION_CHECK_WINDOW) {
+        let artifact = ArtifactId::Commit(commit.clone());
+        if in_progress.contains(&artifact) {
+            continue;
+        }
+        let aid = conn.artifact_id(&artifact).await;
+        if conn.regression_check(aid).await.is_some() {
+            continue;
+        }
+        let master = match master_commits.iter().find(|c| c.sha == commit.sha) {
+            Some(master) => master,
+            None => continue,
+        };
+        if !benchmarked.iter().any(|c| c.sha == master.parent_sha) {
+            continue;
+        }
+
+        let summary = match Summary::compute(data, &master.parent_sha, &commit.sha).await {
+            Ok(summary) => summary,
+            Err(e) => {
+                log::error!("failed to summarize {}: {}", commit.sha, e);
+                continue;
+            }
+        };
+        let pr = match master.pr {
+            Some(pr) => Some(pr),
+            None => conn.pr_of(&commit.sha).await,
+        };
+        let regression = summary.is_regression();
+        if !conn.record_regression_check(aid, pr, regression).await || !regression {
+            continue;
+        }
+
+        if let Some(pr) = pr {
+            let comparison_url = format!(
+                "https://perf.rust-lang.org/compare.html?start={}&end={}",
+                master.parent_sha, commit.sha
+            );
+            post_comment(
+                &data,
+                pr,
+                format!(
+                    "Perf results for the merge of this PR ({}) show a regression \
+against its parent: [comparison url]({}).
+
+{}
+If the regression is expected or has already been addressed, this comment \
+can be ignored.",
+                    commit.sha,
+                    comparison_url,
+                    summary.to_markdown(SUMMARY_TOP_CHANGES)
+                ),
+            )
+            .await;
+        }
+    }
+}
+
+/// Posts a consolidated summary on each unrolled rollup once the try builds
+/// for all of its PRs have been benchmarked.
+pub async fn post_unrolled(data: &InputData) {
+    if data.config.keys.github.is_none() {
+        return;
+    }
+    let mut rollups = BTreeMap::new();
+    for member in data.conn().await.unreported_rollup_members().await {
+        rollups
+            .entry(member.rollup_pr)
+            .or_insert_with(Vec::new)
+            .push(member);
+    }
+
+    for (rollup_pr, members) in rollups {
+        if members.iter().any(|m| m.try_build.is_none()) {
+            continue;
+        }
+
+        let mut table = String::from(
+            "| PR | Regressions | Improvements | Largest change | Comparison |\n\
+            |---|---:|---:|---:|---|\n",
+        );
+        let mut previous: Option<&str> = None;
+        for member in &members {
+            let (sha, parent_sha) = member.try_build.as_ref().unwrap();
+            // Each perf-test build contains the rollup up to and including
+            // its PR, so compare against the build for the PR before it. The
+            // first one is compared against master before the rollup, as the
+            // try build's parent is master at the time of the try build.
+            let base = previous
+                .or_else(|| member.base_sha.as_deref())
+                .unwrap_or(parent_sha);
+            previous = Some(sha);
+            let comparison_url = format!(
+                "https://perf.rust-lang.org/compare.html?start={}&end={}",
+                base, sha
+            );
+            let (regressions, improvements, largest) = match Summary::compute(data, base, sha).await
+            {
+                Ok(summary) => {
+                    let stat = &summary.stats[0];
+                    let largest = stat
+                        .top(1)
+                        .first()
+                        .map(|c| format!("{:+.1}%", c.percent))
+                        .unwrap_or_else(|| String::from("-"));
+                    (
+                        stat.regressions.len().to_string(),
+                        stat.improvements.len().to_string(),
+                        largest,
+                    )
+                }
+                Err(e) => {
+                    log::error!("failed to summarize {}: {}", sha, e);
+                    (String::from("?"), String::from("?"), String::from("?"))
+                }
+            };
+            table.push_str(&format!(
+                "| #{} | {} | {} | {} | [comparison]({}) |\n",
+                member.rolled_up_pr, regressions, improvements, largest, comparison_url
+            ));
+        }
+
+        post_comment(
+            &data,
+            rollup_pr,
+            format!(
+                "Finished benchmarking the unrolled PRs. Each row compares a PR's \
+                perf-test build with the build for the PR merged before it in the \
+                rollup ({}):
+
+{}",
+                SUMMARY_STATS[0], table
+            ),
+        )
+        .await;
+        data.conn().await.mark_rollup_reported(rollup_pr).await;
+    }
+}
@@ -0,0 +1,410 @@
+//! The GitHub (and team API) requests made by the bot, behind a trait so that
+//! the bot can be exercised without talking to GitHub.
+
+use crate::api::{github, ServerResult};
+use anyhow::Context as _;
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use reqwest::header::USER_AGENT;
+
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+
+#[async_trait::async_trait]
+pub trait Client: Send + Sync {
+    /// The base URL of the API, e.g. `https://api.github.com`.
+    fn api_url(&self) -> &str;
+
+    /// Returns the GitHub ids of the users allowed to issue commands.
+    async fn authorized_users(&self) -> ServerResult<Vec<usize>>;
+
+    /// Posts a comment on a rust-lang/rust issue or PR. Failures are logged.
+    async fn post_comment(&self, pr: u32, body: String);
+
+    async fn create_ref(&self, repository_url: &str, ref_: &str, sha: &str) -> anyhow::Result<()>;
+
+    async fn create_pr(
+        &self,
+        repository_url: &str,
+        title: &str,
+        head: &str,
+        base: &str,
+        description: &str,
+    ) -> anyhow::Result<CreatePrResponse>;
+
+    /// Creates a commit and returns its sha.
+    async fn create_commit(
+        &self,
+        repository_url: &str,
+        message: &str,
+        tree: &str,
+        parents: &[&str],
+    ) -> anyhow::Result<String>;
+
+    /// Fetches a commit by sha, or by branch name.
+    async fn get_commit(&self, repository_url: &str, sha: &str) -> anyhow::Result<github::Commit>;
+}
+
+#[derive(serde::Serialize)]
+struct CreateRefRequest<'a> {
+    // Must start with `refs/` and have at least two slashes.
+    // e.g. `refs/heads/master`.
+    #[serde(rename = "ref")]
+    ref_: &'a str,
+    sha: &'a str,
+}
+
+#[derive(serde::Serialize)]
+struct CreatePrRequest<'a> {
+    title: &'a str,
+    // username:branch if cross-repo
+    head: &'a str,
+    // branch to pull into (e.g, master)
+    base: &'a str,
+    #[serde(rename = "body")]
+    description: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct CreatePrResponse {
+    pub number: u32,
+    pub html_url: String,
+    pub comments_url: String,
+}
+
+#[derive(serde::Serialize)]
+struct CreateCommitRequest<'a> {
+    message: &'a str,
+    tree: &'a str,
+    parents: &'a [&'a str],
+}
+
+#[derive(serde::Deserialize)]
+struct CreateCommitResponse {
+    sha: String,
+}
+
+/// The client used in production, which talks to the GitHub API as rust-timer.
+pub struct HttpClient {
+    client: reqwest::Client,
+    api_url: String,
+    token: Option<String>,
+}
+
+impl HttpClient {
+    pub fn new(api_url: &str, token: Option<String>) -> HttpClient {
+        HttpClient {
+            client: reqwest::Client::new(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    fn token(&self) -> String {
+        self.token.clone().expect("needs rust-timer token")
+    }
+}
+
+#[async_trait::async_trait]
+impl Client for HttpClient {
+    fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    async fn authorized_users(&self) -> ServerResult<Vec<usize>> {
+        let url = format!("{}/permissions/perf.json", ::rust_team_data::v1::BASE_URL);
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|err| format!("failed to fetch authorized users: {}", err))?
+            .error_for_status()
+            .map_err(|err| format!("failed to fetch authorized users: {}", err))?
+            .json::<rust_team_data::v1::Permission>()
+            .await
+            .map_err(|err| format!("failed to fetch authorized users: {}", err))
+            .map(|perms| perms.github_ids)
+    }
+
+    async fn post_comment(&self, pr: u32, body: String) {
+        let req = self
+            .client
+            .post(&format!(
+                "{}/repos/rust-lang/rust/issues/{}/comments",
+                self.api_url, pr
+            ))
+            .json(&github::PostComment { body })
+            .header(USER_AGENT, "perf-rust-lang-org-server")
+            .basic_auth("rust-timer", Some(self.token()));
+
+        if let Err(e) = req.send().await {
+            eprintln!("failed to post comment: {:?}", e);
+        }
+    }
+
+    async fn create_ref(&self, repository_url: &str, ref_: &str, sha: &str) -> anyhow::Result<()> {
+        let url = format!("{}/git/refs", repository_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&CreateRefRequest { ref_, sha })
+            .header(USER_AGENT, "perf-rust-lang-org-server")
+            .basic_auth("rust-timer", Some(self.token()))
+            .send()
+            .await
+            .context("POST git/refs failed")?;
+        if response.status() != reqwest::StatusCode::CREATED {
+            anyhow::bail!("{:?} != 201 CREATED", response.status());
+        }
+
+        Ok(())
+    }
+
+    async fn create_pr(
+        &self,
+        repository_url: &str,
+        title: &str,
+        head: &str,
+        base: &str,
+        description: &str,
+    ) -> anyhow::Result<CreatePrResponse> {
+        let url = format!("{}/pulls", repository_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&CreatePrRequest {
+                title,
+                head,
+                base,
+                description,
+            })
+            .header(USER_AGENT, "perf-rust-lang-org-server")
+            .basic_auth("rust-timer", Some(self.token()))
+            .send()
+            .await
+            .context("POST pulls failed")?;
+        if response.status() != reqwest::StatusCode::CREATED {
+            anyhow::bail!("{:?} != 201 CREATED", response.status());
+        }
+
+        Ok(response.json().await.context("deserializing failed")?)
+    }
+
+    async fn create_commit(
+        &self,
+        repository_url: &str,
+        message: &str,
+        tree: &str,
+        parents: &[&str],
+    ) -> anyhow::Result<String> {
+        let url = format!("{}/git/commits", repository_url);
+        let commit_response = self
+            .client
+            .post(&url)
+            .json(&CreateCommitRequest {
+                message,
+                tree,
+                parents,
+            })
+            .header(USER_AGENT, "perf-rust-lang-org-server")
+            .basic_auth("rust-timer", Some(self.token()))
+            .send()
+            .await
+            .context("POST git/commits failed")?;
+        if commit_response.status() != reqwest::StatusCode::CREATED {
+            anyhow::bail!("{:?} != 201 CREATED", commit_response.status());
+        }
+
+        Ok(commit_response
+            .json::<CreateCommitResponse>()
+            .await
+            .context("deserializing failed")?
+            .sha)
+    }
+
+    async fn get_commit(&self, repository_url: &str, sha: &str) -> anyhow::Result<github::Commit> {
+        let url = format!("{}/commits/{}", repository_url, sha);
+        let commit_response = self
+            .client
+            .get(&url)
+            .header(USER_AGENT, "perf-rust-lang-org-server")
+            .basic_auth("rust-timer", Some(self.token()))
+            .send()
+            .await
+            .context("cannot get commit")?;
+        let commit_response = match commit_response.text().await {
+            Ok(c) => c,
+            Err(err) => {
+                anyhow::bail!("Failed to decode response for {}: {:?}", url, err);
+            }
+        };
+        match serde_json::from_str(&commit_response) {
+            Ok(c) => Ok(c),
+            Err(e) => Err(anyhow::anyhow!(
+                "cannot deserialize commit ({}): {:?}",
+                commit_response,
+                e
+            )),
+        }
+    }
+}
+
+/// A request made through a `RecordingClient`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recorded {
+    Comment {
+        pr: u32,
+        body: String,
+    },
+    CreateRef {
+        repository_url: String,
+        ref_: String,
+        sha: String,
+    },
+    CreatePr {
+        repository_url: String,
+        title: String,
+        head: String,
+        base: String,
+        number: u32,
+    },
+    CreateCommit {
+        repository_url: String,
+        message: String,
+        parents: Vec<String>,
+        sha: String,
+    },
+}
+
+/// An in-memory client which records requests instead of sending them, for
+/// testing. Commits must be registered with `add_commit` before they can be
+/// fetched; created commits and PRs get made-up shas and numbers.
+#[derive(Default)]
+pub struct RecordingClient {
+    authorized_users: Mutex<Vec<usize>>,
+    commits: Mutex<HashMap<String, github::Commit>>,
+    requests: Mutex<Vec<Recorded>>,
+}
+
+impl RecordingClient {
+    pub fn new() -> RecordingClient {
+        RecordingClient::default()
+    }
+
+    pub fn authorize(&self, user: usize) {
+        self.authorized_users.lock().push(user);
+    }
+
+    /// Makes `commit` available under `name`, which is either its sha or a
+    /// branch name.
+    pub fn add_commit(&self, name: &str, commit: github::Commit) {
+        self.commits.lock().insert(name.to_string(), commit);
+    }
+
+    /// All requests made so far, oldest first.
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().clone()
+    }
+
+    /// The bodies of the comments posted on `pr`, oldest first.
+    pub fn comments(&self, pr: u32) -> Vec<String> {
+        self.requests
+            .lock()
+            .iter()
+            .filter_map(|r| match r {
+                Recorded::Comment { pr: p, body } if *p == pr => Some(body.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn record(&self, request: Recorded) {
+        self.requests.lock().push(request);
+    }
+}
+
+#[async_trait::async_trait]
+impl Client for RecordingClient {
+    fn api_url(&self) -> &str {
+        DEFAULT_API_URL
+    }
+
+    async fn authorized_users(&self) -> ServerResult<Vec<usize>> {
+        Ok(self.authorized_users.lock().clone())
+    }
+
+    async fn post_comment(&self, pr: u32, body: String) {
+        self.record(Recorded::Comment { pr, body });
+    }
+
+    async fn create_ref(&self, repository_url: &str, ref_: &str, sha: &str) -> anyhow::Result<()> {
+        self.record(Recorded::CreateRef {
+            repository_url: repository_url.to_string(),
+            ref_: ref_.to_string(),
+            sha: sha.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn create_pr(
+        &self,
+        repository_url: &str,
+        title: &str,
+        head: &str,
+        base: &str,
+        _description: &str,
+    ) -> anyhow::Result<CreatePrResponse> {
+        let mut requests = self.requests.lock();
+        let number = 100_000 + requests.len() as u32;
+        requests.push(Recorded::CreatePr {
+            repository_url: repository_url.to_string(),
+            title: title.to_string(),
+            head: head.to_string(),
+            base: base.to_string(),
+            number,
+        });
+        Ok(CreatePrResponse {
+            number,
+            html_url: format!("{}/pull/{}", repository_url, number),
+            comments_url: format!("{}/issues/{}/comments", repository_url, number),
+        })
+    }
+
+    async fn create_commit(
+        &self,
+        repository_url: &str,
+        message: &str,
+        tree: &str,
+        parents: &[&str],
+    ) -> anyhow::Result<String> {
+        let sha = format!("recorded-commit-{}", self.requests.lock().len());
+        let commit = github::Commit {
+            sha: sha.clone(),
+            commit: github::InnerCommit {
+                message: message.to_string(),
+                tree: github::CommitTree {
+                    sha: tree.to_string(),
+                },
+            },
+            parents: parents
+                .iter()
+                .map(|p| github::CommitParent { sha: p.to_string() })
+                .collect(),
+        };
+        self.add_commit(&sha, commit);
+        self.record(Recorded::CreateCommit {
+            repository_url: repository_url.to_string(),
+            message: message.to_string(),
+            parents: parents.iter().map(|p| p.to_string()).collect(),
+            sha: sha.clone(),
+        });
+        Ok(sha)
+    }
+
+    async fn get_commit(&self, _repository_url: &str, sha: &str) -> anyhow::Result<github::Commit> {
+        self.commits
+            .lock()
+            .get(sha)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("unknown commit {}", sha))
+    }
+}
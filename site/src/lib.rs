@@ -20,8 +20,11 @@ pub struct CommandFailed {
 
 pub mod github;
 
+pub mod aggregate;
 pub mod api;
 mod average;
+mod changepoint;
+pub mod comparison;
 pub mod db;
 mod interpolate;
 pub mod load;
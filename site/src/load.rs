@@ -14,18 +14,18 @@ use std::ops::RangeInclusive;
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::Context;
 use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::db;
+use crate::github::client::{self as github_client, Client as GithubClient};
 use crate::util;
 use collector::Bound;
 use database::Date;
 
 use crate::api::github;
 use collector;
-use database::Pool;
+use database::{Annotation, MasterCommit, Pool};
 pub use database::{ArtifactId, Commit, Crate};
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
@@ -40,6 +40,13 @@ pub enum MissingReason {
         include: Option<String>,
         exclude: Option<String>,
         runs: Option<i32>,
+        builds: Option<String>,
+        scenarios: Option<String>,
+        high_priority: bool,
+    },
+    /// Queued through the artifact queue.
+    Artifact {
+        local_rustc: Option<String>,
     },
     InProgress(Option<Box<MissingReason>>),
 }
@@ -73,6 +80,56 @@ pub struct Keys {
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub keys: Keys,
+    /// The base URL of the GitHub API, if not `https://api.github.com`.
+    #[serde(default)]
+    pub github_api_url: Option<String>,
+    /// The aggregate indices to compute for every artifact.
+    #[serde(default, rename = "aggregate_index")]
+    pub aggregate_indices: Vec<AggregateIndex>,
+}
+
+/// A single number summarizing the performance of an artifact relative to a
+/// baseline, computed by `crate::aggregate`. For example:
+///
+/// ```toml
+/// [[aggregate_index]]
+/// name = "headline"
+/// baseline = "1.45.0"
+///
+/// [[aggregate_index.category]]
+/// name = "real-world"
+/// weight = 2.0
+/// benchmarks = ["cargo", "syn", "regex"]
+///
+/// [[aggregate_index.category]]
+/// name = "stress"
+/// weight = 1.0
+/// benchmarks = ["deep-vector", "tuple-stress"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct AggregateIndex {
+    /// The values are persisted under this name, so it should change along
+    /// with the definition of the index.
+    pub name: String,
+    /// The artifact (or commit) the others are compared against.
+    pub baseline: String,
+    #[serde(default = "default_aggregate_stat")]
+    pub stat: String,
+    #[serde(rename = "category")]
+    pub categories: Vec<BenchmarkCategory>,
+}
+
+fn default_aggregate_stat() -> String {
+    String::from("instructions:u")
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BenchmarkCategory {
+    pub name: String,
+    /// How much the category counts towards the index, relative to the other
+    /// categories.
+    pub weight: f64,
+    pub benchmarks: Vec<String>,
 }
 
 pub struct InputData {
@@ -82,6 +139,8 @@ pub struct InputData {
 
     pub index: ArcSwap<crate::db::Index>,
     pub pool: Pool,
+
+    pub github: Arc<dyn GithubClient>,
 }
 
 impl InputData {
@@ -118,9 +177,6 @@ impl InputData {
 
         let pool = Pool::open(db);
 
-        let mut conn = pool.connection().await;
-        let index = db::Index::load(&mut *conn).await;
-
         let config = if let Ok(s) = fs::read_to_string("site-config.toml") {
             toml::from_str(&s)?
         } else {
@@ -129,38 +185,58 @@ impl InputData {
                     github: std::env::var("GITHUB_API_TOKEN").ok(),
                     secret: std::env::var("GITHUB_WEBHOOK_SECRET").ok(),
                 },
+                github_api_url: std::env::var("GITHUB_API_URL").ok(),
+                aggregate_indices: Vec::new(),
             }
         };
+        let github = github_client::HttpClient::new(
+            config
+                .github_api_url
+                .as_deref()
+                .unwrap_or(github_client::DEFAULT_API_URL),
+            config.keys.github.clone(),
+        );
+
+        Ok(InputData::new(pool, config, Arc::new(github)).await)
+    }
+
+    /// Initialize `InputData` from an open database, talking to GitHub
+    /// through `github`.
+    pub async fn new(pool: Pool, config: Config, github: Arc<dyn GithubClient>) -> InputData {
+        let mut conn = pool.connection().await;
+        let index = db::Index::load(&mut *conn).await;
 
-        Ok(InputData {
+        InputData {
             config,
             index: ArcSwap::new(Arc::new(index)),
             pool,
             landing_page: ArcSwap::new(Arc::new(None)),
-        })
+            github,
+        }
     }
 
     pub async fn conn(&self) -> Box<dyn database::pool::Connection> {
         self.pool.connection().await
     }
 
-    pub async fn missing_commits(&self) -> Vec<(Commit, MissingReason)> {
+    /// The commits and artifacts waiting to be benchmarked, in the order they
+    /// should be: those in progress, try builds (preceded by their parent if
+    /// necessary), queued artifacts and finally recent master commits.
+    pub async fn missing_commits(&self) -> Vec<(ArtifactId, MissingReason)> {
         let conn = self.conn().await;
-        let (master_commits, queued_commits, in_progress_artifacts) = futures::join!(
-            rustc_artifacts::master_commits(),
+        let (commits, queued_commits, queued_artifacts, in_progress_artifacts) = futures::join!(
+            conn.master_commits(),
             conn.queued_commits(),
+            conn.queued_artifacts(),
             conn.in_progress_artifacts()
         );
-        let commits = master_commits
-            .map_err(|e| anyhow::anyhow!("{:?}", e))
-            .context("getting master commit list")
-            .unwrap();
 
         let index = self.index.load();
         let mut have = index
             .commits()
             .iter()
             .map(|commit| commit.sha.clone())
+            .chain(index.artifacts().map(String::from))
             .collect::<HashSet<_>>();
 
         let now = Utc::now();
@@ -181,7 +257,6 @@ impl InputData {
                 )
             })
             .collect::<Vec<_>>();
-        missing.reverse();
         let mut commits = Vec::new();
         commits.reserve(queued_commits.len() * 2); // Two commits per every try commit
         for database::QueuedCommit {
@@ -191,44 +266,54 @@ impl InputData {
             include,
             exclude,
             runs,
+            builds,
+            scenarios,
+            high_priority,
         } in queued_commits
         {
             // Enqueue the `TryParent` commit before the `TryCommit` itself, so that
             // all of the `try` run's data is complete when the benchmark results
             // of that commit are available.
             if let Some((commit, _)) = missing.iter().find(|c| c.0.sha == *parent_sha.as_str()) {
-                commits.push((commit.clone(), MissingReason::TryParent));
+                commits.push((ArtifactId::Commit(commit.clone()), MissingReason::TryParent));
             }
             commits.push((
-                Commit {
+                ArtifactId::Commit(Commit {
                     sha: sha.to_string(),
                     date: Date::ymd_hms(2001, 01, 01, 0, 0, 0),
-                },
+                }),
                 MissingReason::Try {
                     pr,
                     include,
                     exclude,
                     runs,
+                    builds,
+                    scenarios,
+                    high_priority,
                 },
             ));
         }
-        commits.extend(missing);
+        commits.extend(queued_artifacts.into_iter().map(|artifact| {
+            (
+                ArtifactId::Artifact(artifact.name),
+                MissingReason::Artifact {
+                    local_rustc: artifact.local_rustc,
+                },
+            )
+        }));
+        commits.extend(
+            missing
+                .into_iter()
+                .map(|(commit, reason)| (ArtifactId::Commit(commit), reason)),
+        );
 
         for aid in in_progress_artifacts {
-            match aid {
-                ArtifactId::Commit(c) => {
-                    let previous = commits
-                        .iter()
-                        .find(|(i, _)| i.sha == c.sha)
-                        .map(|v| Box::new(v.1.clone()));
-                    have.remove(&c.sha);
-                    commits.insert(0, (c, MissingReason::InProgress(previous)));
-                }
-                ArtifactId::Artifact(_) => {
-                    // do nothing, for now, though eventually we'll want an artifact
-                    // queue
-                }
-            }
+            let previous = commits
+                .iter()
+                .find(|(i, _)| i.name() == aid.name())
+                .map(|v| Box::new(v.1.clone()));
+            have.remove(aid.name());
+            commits.insert(0, (aid, MissingReason::InProgress(previous)));
         }
 
         let mut seen = HashSet::with_capacity(commits.len());
@@ -237,7 +322,7 @@ impl InputData {
         // FIXME: replace with Vec::drain_filter when it stabilizes
         let mut i = 0;
         while i != commits.len() {
-            if !seen.insert(commits[i].0.sha.clone()) {
+            if !seen.insert(commits[i].0.name().to_string()) {
                 commits.remove(i);
             } else {
                 i += 1;
@@ -246,6 +331,91 @@ impl InputData {
 
         commits
     }
+
+    /// The master commits, most recent first, as last cached by
+    /// `refresh_master_commits`.
+    pub async fn master_commits(&self) -> Vec<MasterCommit> {
+        self.conn().await.master_commits().await
+    }
+
+    /// Fetches the list of recent master commits and caches it in the
+    /// database. If it can't be fetched, e.g. when offline, the cached list
+    /// keeps being used.
+    pub async fn refresh_master_commits(&self) {
+        let commits = match rustc_artifacts::master_commits().await {
+            Ok(commits) => commits,
+            Err(e) => {
+                log::warn!("failed to fetch master commits: {:?}", e);
+                return;
+            }
+        };
+        let commits = commits
+            .into_iter()
+            .map(|c| MasterCommit {
+                sha: c.sha,
+                parent_sha: c.parent_sha,
+                pr: c.pr,
+                time: c.time,
+            })
+            .collect::<Vec<_>>();
+        self.conn().await.record_master_commits(&commits).await;
+    }
+
+    /// The annotations covering any of the artifacts from `a` to `b`.
+    pub async fn annotations_between(&self, a: &ArtifactId, b: &ArtifactId) -> Vec<Annotation> {
+        self.conn()
+            .await
+            .annotations()
+            .await
+            .into_iter()
+            .filter(|annotation| self.annotation_overlaps(annotation, a, b))
+            .collect()
+    }
+
+    /// Whether `annotation` covers any of the artifacts from `a` to `b`. Only
+    /// commits are ordered, so otherwise the annotation has to start or end
+    /// at `a` or `b`.
+    pub fn annotation_overlaps(
+        &self,
+        annotation: &Annotation,
+        a: &ArtifactId,
+        b: &ArtifactId,
+    ) -> bool {
+        let date = |name: &str| match self.data_for(true, Bound::Commit(name.to_string())) {
+            Some(ArtifactId::Commit(c)) => Some(c.date),
+            _ => None,
+        };
+        match (a, b, date(&annotation.start), date(&annotation.end)) {
+            (ArtifactId::Commit(a), ArtifactId::Commit(b), Some(start), Some(end)) => {
+                // Try builds are placed at their parent.
+                let b = if b.is_try() { a } else { b };
+                start <= b.date && a.date <= end
+            }
+            _ => [a, b]
+                .iter()
+                .any(|aid| aid.name() == annotation.start || aid.name() == annotation.end),
+        }
+    }
+
+    /// Takes the queued artifacts which have been benchmarked out of the
+    /// artifact queue.
+    pub async fn complete_artifacts(&self) {
+        let benchmarked = self
+            .index
+            .load()
+            .artifacts()
+            .map(String::from)
+            .collect::<HashSet<_>>();
+        let conn = self.conn().await;
+        let in_progress = conn.in_progress_artifacts().await;
+        for artifact in conn.queued_artifacts().await {
+            if benchmarked.contains(&artifact.name)
+                && !in_progress.iter().any(|a| a.name() == artifact.name)
+            {
+                conn.mark_artifact_complete(&artifact.name).await;
+            }
+        }
+    }
 }
 
 /// One decimal place rounded percent
@@ -14,10 +14,14 @@ use parking_lot::RwLock;
 use site::load;
 use std::env;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[global_allocator]
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
+/// How often the cached list of master commits is refreshed.
+const MASTER_COMMITS_REFRESH: Duration = Duration::from_secs(10 * 60);
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
@@ -47,9 +51,17 @@ async fn main() {
                 commits, artifacts,
             );
             eprintln!("View the results in a web browser at 'localhost:2346/compare.html'");
+            let refresh = res.clone();
+            tokio::spawn(async move {
+                loop {
+                    refresh.refresh_master_commits().await;
+                    tokio::time::delay_for(MASTER_COMMITS_REFRESH).await;
+                }
+            });
             // Spawn off a task to post the results of any commit results that we
-            // are now aware of.
+            // are now aware of, and to compute missing aggregate indices.
             site::github::post_finished(&res).await;
+            site::aggregate::update(&res).await;
         })
     })
     .fuse();
@@ -519,6 +519,54 @@ impl Db {
     {
         E::query(self, collection_ids, query).await
     }
+
+    /// The number of series `query` selects, counted from the index without
+    /// loading any of their data. As with `query`, these are statistics, or
+    /// self-profile query times if a `QueryLabel` selector is given.
+    pub fn count_series(&self, mut query: Query) -> Result<usize, String> {
+        let krate = query.extract(Tag::Crate)?.raw;
+        let profile = query
+            .extract(Tag::Profile)?
+            .raw
+            .try_map(|p| p.parse::<Profile>())?;
+        let cache = query
+            .extract(Tag::Cache)?
+            .raw
+            .try_map(|p| p.parse::<Cache>())?;
+
+        let index = self.index.load();
+        if query.get(Tag::QueryLabel).is_ok() {
+            let ql = query
+                .extract(Tag::QueryLabel)?
+                .raw
+                .map(|p| QueryLabel::from(p.as_str()));
+            query.assert_empty()?;
+            Ok(index
+                .all_query_series()
+                .filter(|tup| {
+                    krate.matches(tup.0)
+                        && profile.matches(tup.1)
+                        && cache.matches(tup.2)
+                        && ql.matches(tup.3)
+                })
+                .count())
+        } else {
+            let statid = query
+                .extract(Tag::ProcessStatistic)?
+                .raw
+                .try_map(|p| p.parse::<ProcessStatistic>())?;
+            query.assert_empty()?;
+            Ok(index
+                .all_pstat_series()
+                .filter(|tup| {
+                    krate.matches(tup.0)
+                        && profile.matches(tup.1)
+                        && cache.matches(tup.2)
+                        && statid.matches(tup.3)
+                })
+                .count())
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -13,6 +13,10 @@ type Response = http::Response<hyper::Body>;
 
 pub mod crox;
 pub mod flamegraph;
+pub mod perfetto;
+pub mod speedscope;
+pub mod summarize;
+mod timeline;
 
 pub struct Output {
     pub data: Vec<u8>,
@@ -20,9 +24,13 @@ pub struct Output {
     pub is_download: bool,
 }
 
+/// Generates the output selected by the `type` parameter. `base_pieces` are
+/// the profile of the base artifact, which the differential outputs compare
+/// against.
 pub fn generate(
     title: &str,
     pieces: Pieces,
+    base_pieces: Option<Pieces>,
     mut params: HashMap<String, String>,
 ) -> anyhow::Result<Output> {
     let removed = params.remove("type");
@@ -45,7 +53,49 @@ pub fn generate(
                 is_download: false,
             })
         }
-        _ => anyhow::bail!("Unknown type, specify type={crox,flamegraph}"),
+        Some("flamegraph-diff") => {
+            let base_pieces =
+                base_pieces.context("flamegraph-diff needs a base_commit to compare against")?;
+            let opt = serde_json::from_str(&serde_json::to_string(&params).unwrap())
+                .context("flame opts")?;
+            Ok(Output {
+                filename: "flamegraph-diff.svg",
+                data: flamegraph::generate_diff(title, base_pieces, pieces, opt)
+                    .context("flame diff")?,
+                is_download: false,
+            })
+        }
+        Some("speedscope") => {
+            let opt = serde_json::from_str(&serde_json::to_string(&params).unwrap())
+                .context("speedscope opts")?;
+            Ok(Output {
+                filename: "speedscope.json",
+                data: speedscope::generate(title, pieces, opt).context("speedscope")?,
+                is_download: true,
+            })
+        }
+        Some("perfetto") => {
+            let opt = serde_json::from_str(&serde_json::to_string(&params).unwrap())
+                .context("perfetto opts")?;
+            Ok(Output {
+                filename: "trace.perfetto-trace",
+                data: perfetto::generate(pieces, opt).context("perfetto")?,
+                is_download: true,
+            })
+        }
+        Some("summarize") => {
+            let opt = serde_json::from_str(&serde_json::to_string(&params).unwrap())
+                .context("summarize opts")?;
+            Ok(Output {
+                filename: "summary.txt",
+                data: summarize::generate(pieces, opt).context("summarize")?,
+                is_download: false,
+            })
+        }
+        _ => anyhow::bail!(
+            "Unknown type, specify \
+            type={crox,flamegraph,flamegraph-diff,speedscope,perfetto,summarize}"
+        ),
     }
 }
 
@@ -1,31 +1,59 @@
 use analyzeme::{collapse_stacks, ProfilingData};
 use anyhow::Context;
+use inferno::differential::{from_readers, Options as DifferentialOptions};
 use inferno::flamegraph::{from_lines, Options as FlamegraphOptions};
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Opt {}
 
 pub fn generate(title: &str, pieces: super::Pieces, _: Opt) -> anyhow::Result<Vec<u8>> {
+    let recorded_stacks = collapsed_stacks(pieces)?;
+    render(title, recorded_stacks.iter().map(|s| s.as_ref()))
+}
+
+/// Renders a differential flamegraph: the frames are sized by their time in
+/// `pieces`, and colored by the change from `base` (red for slower, blue for
+/// faster).
+pub fn generate_diff(
+    title: &str,
+    base: super::Pieces,
+    pieces: super::Pieces,
+    _: Opt,
+) -> anyhow::Result<Vec<u8>> {
+    let before = collapsed_stacks(base)?.join("\n");
+    let after = collapsed_stacks(pieces)?.join("\n");
+
+    let mut folded = Vec::new();
+    from_readers(
+        DifferentialOptions::default(),
+        before.as_bytes(),
+        after.as_bytes(),
+        &mut folded,
+    )
+    .context("unable to compute the differential stack data")?;
+    let folded = String::from_utf8(folded).context("differential stack data")?;
+    render(title, folded.lines())
+}
+
+fn collapsed_stacks(pieces: super::Pieces) -> anyhow::Result<Vec<String>> {
     let profiling_data =
         ProfilingData::from_buffers(pieces.string_data, pieces.string_index, pieces.events)
             .map_err(|e| anyhow::format_err!("{:?}", e))?;
 
-    let recorded_stacks = collapse_stacks(&profiling_data)
+    Ok(collapse_stacks(&profiling_data)
         .iter()
         .map(|(unique_stack, count)| format!("{} {}", unique_stack, count))
-        .collect::<Vec<_>>();
+        .collect())
+}
 
+fn render<'a>(title: &str, lines: impl IntoIterator<Item = &'a str>) -> anyhow::Result<Vec<u8>> {
     let mut file = Vec::new();
     let mut flamegraph_options = FlamegraphOptions::default();
     flamegraph_options.count_name = "nanoseconds".to_owned();
     flamegraph_options.title = title.to_owned();
     flamegraph_options.min_width = 0.0;
 
-    from_lines(
-        &mut flamegraph_options,
-        recorded_stacks.iter().map(|s| s.as_ref()),
-        &mut file,
-    )
-    .context("unable to generate a flamegraph from the collapsed stack data")?;
+    from_lines(&mut flamegraph_options, lines, &mut file)
+        .context("unable to generate a flamegraph from the collapsed stack data")?;
     Ok(file)
 }
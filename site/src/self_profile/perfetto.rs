@@ -0,0 +1,203 @@
+//! Conversion to a Perfetto protobuf trace (see
+//! https://perfetto.dev/docs/reference/trace-packet-proto), with a track per
+//! thread. Only the few messages needed are encoded, by hand.
+
+use super::timeline::{Boundary, Timeline};
+use analyzeme::ProfilingData;
+use std::time::UNIX_EPOCH;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Opt {}
+
+// Field numbers, from the Perfetto protos.
+const TRACE_PACKET: u32 = 1;
+const PACKET_TIMESTAMP: u32 = 8;
+const PACKET_SEQUENCE_ID: u32 = 10;
+const PACKET_TRACK_EVENT: u32 = 11;
+const PACKET_SEQUENCE_FLAGS: u32 = 13;
+const PACKET_TRACK_DESCRIPTOR: u32 = 60;
+const TRACK_UUID: u32 = 1;
+const TRACK_NAME: u32 = 2;
+const TRACK_PROCESS: u32 = 3;
+const TRACK_THREAD: u32 = 4;
+const TRACK_PARENT_UUID: u32 = 5;
+const PROCESS_PID: u32 = 1;
+const PROCESS_NAME: u32 = 6;
+const THREAD_PID: u32 = 1;
+const THREAD_TID: u32 = 2;
+const THREAD_NAME: u32 = 5;
+const EVENT_CATEGORIES: u32 = 22;
+const EVENT_TYPE: u32 = 9;
+const EVENT_TRACK_UUID: u32 = 11;
+const EVENT_NAME: u32 = 23;
+
+const SEQUENCE_ID: u64 = 1;
+const SEQ_INCREMENTAL_STATE_CLEARED: u64 = 1;
+const TYPE_SLICE_BEGIN: u64 = 1;
+const TYPE_SLICE_END: u64 = 2;
+const PROCESS_UUID: u64 = 1;
+
+/// A protobuf message being encoded.
+#[derive(Default)]
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn varint(&mut self, field: u32, value: u64) -> &mut Self {
+        write_varint(&mut self.buf, u64::from(field) << 3);
+        write_varint(&mut self.buf, value);
+        self
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Self {
+        write_varint(&mut self.buf, u64::from(field) << 3 | 2);
+        write_varint(&mut self.buf, value.len() as u64);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    fn message(&mut self, field: u32, value: &Message) -> &mut Self {
+        self.bytes(field, &value.buf)
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+pub fn generate(pieces: super::Pieces, _: Opt) -> anyhow::Result<Vec<u8>> {
+    let data = ProfilingData::from_buffers(pieces.string_data, pieces.string_index, pieces.events)
+        .map_err(|e| anyhow::format_err!("{:?}", e))?;
+    let start = data
+        .metadata
+        .start_time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let pid = u64::from(data.metadata.process_id);
+
+    let mut trace = Message::default();
+    let mut process = Message::default();
+    process
+        .varint(PROCESS_PID, pid)
+        .string(PROCESS_NAME, "rustc");
+    let mut track = Message::default();
+    track
+        .varint(TRACK_UUID, PROCESS_UUID)
+        .message(TRACK_PROCESS, &process);
+    let mut packet = Message::default();
+    packet
+        .varint(PACKET_SEQUENCE_ID, SEQUENCE_ID)
+        .varint(PACKET_SEQUENCE_FLAGS, SEQ_INCREMENTAL_STATE_CLEARED)
+        .message(PACKET_TRACK_DESCRIPTOR, &track);
+    trace.message(TRACE_PACKET, &packet);
+
+    for timeline in Timeline::from_profile(&data) {
+        let track_uuid = PROCESS_UUID + 1 + u64::from(timeline.thread_id);
+        let name = format!("thread {}", timeline.thread_id);
+        let mut thread = Message::default();
+        thread
+            .varint(THREAD_PID, pid)
+            .varint(THREAD_TID, u64::from(timeline.thread_id))
+            .string(THREAD_NAME, &name);
+        let mut track = Message::default();
+        track
+            .varint(TRACK_UUID, track_uuid)
+            .varint(TRACK_PARENT_UUID, PROCESS_UUID)
+            .string(TRACK_NAME, &name)
+            .message(TRACK_THREAD, &thread);
+        let mut packet = Message::default();
+        packet
+            .varint(PACKET_SEQUENCE_ID, SEQUENCE_ID)
+            .message(PACKET_TRACK_DESCRIPTOR, &track);
+        trace.message(TRACE_PACKET, &packet);
+
+        for boundary in timeline.boundaries() {
+            let mut event = Message::default();
+            event.varint(EVENT_TRACK_UUID, track_uuid);
+            let at = match boundary {
+                Boundary::Begin { interval, at } => {
+                    let interval = &timeline.intervals[interval];
+                    event
+                        .varint(EVENT_TYPE, TYPE_SLICE_BEGIN)
+                        .string(EVENT_NAME, &interval.label)
+                        .string(EVENT_CATEGORIES, &interval.kind);
+                    at
+                }
+                Boundary::End { at, .. } => {
+                    event.varint(EVENT_TYPE, TYPE_SLICE_END);
+                    at
+                }
+            };
+            let mut packet = Message::default();
+            packet
+                .varint(PACKET_TIMESTAMP, start + at)
+                .varint(PACKET_SEQUENCE_ID, SEQUENCE_ID)
+                .message(PACKET_TRACK_EVENT, &event);
+            trace.message(TRACE_PACKET, &packet);
+        }
+    }
+
+    Ok(trace.buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(value: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, value);
+        buf
+    }
+
+    #[test]
+    fn varints() {
+        assert_eq!(varint(0), [0x00]);
+        assert_eq!(varint(1), [0x01]);
+        assert_eq!(varint(127), [0x7f]);
+        assert_eq!(varint(128), [0x80, 0x01]);
+        assert_eq!(varint(300), [0xac, 0x02]);
+        assert_eq!(
+            varint(u64::MAX),
+            [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+        );
+    }
+
+    // The examples of https://developers.google.com/protocol-buffers/docs/encoding.
+    #[test]
+    fn fields() {
+        let mut message = Message::default();
+        message.varint(1, 150);
+        assert_eq!(message.buf, [0x08, 0x96, 0x01]);
+
+        let mut message = Message::default();
+        message.string(2, "testing");
+        assert_eq!(message.buf, b"\x12\x07testing");
+
+        let mut inner = Message::default();
+        inner.varint(1, 150);
+        let mut message = Message::default();
+        message.message(3, &inner);
+        assert_eq!(message.buf, [0x1a, 0x03, 0x08, 0x96, 0x01]);
+    }
+
+    #[test]
+    fn fields_are_appended_in_order() {
+        let mut message = Message::default();
+        message
+            .varint(PACKET_SEQUENCE_ID, SEQUENCE_ID)
+            .bytes(PACKET_TRACK_DESCRIPTOR, &[]);
+        // Field 60 needs a two byte tag.
+        assert_eq!(message.buf, [0x50, 0x01, 0xe2, 0x03, 0x00]);
+    }
+}
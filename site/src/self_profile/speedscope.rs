@@ -0,0 +1,109 @@
+//! Conversion to speedscope's file format
+//! (https://www.speedscope.app/file-format-schema.json), with one evented
+//! profile per thread.
+
+use super::timeline::{Boundary, Timeline};
+use analyzeme::ProfilingData;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Opt {}
+
+#[derive(Serialize)]
+struct File<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    shared: Shared,
+    profiles: Vec<Profile>,
+    name: &'a str,
+    exporter: &'static str,
+}
+
+#[derive(Serialize)]
+struct Shared {
+    frames: Vec<Frame>,
+}
+
+#[derive(Serialize)]
+struct Frame {
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Profile {
+    #[serde(rename = "type")]
+    ty: &'static str,
+    name: String,
+    unit: &'static str,
+    start_value: u64,
+    end_value: u64,
+    events: Vec<Event>,
+}
+
+#[derive(Serialize)]
+struct Event {
+    /// "O" for opening a frame, "C" for closing it.
+    #[serde(rename = "type")]
+    ty: &'static str,
+    frame: usize,
+    at: u64,
+}
+
+pub fn generate(title: &str, pieces: super::Pieces, _: Opt) -> anyhow::Result<Vec<u8>> {
+    let data = ProfilingData::from_buffers(pieces.string_data, pieces.string_index, pieces.events)
+        .map_err(|e| anyhow::format_err!("{:?}", e))?;
+
+    let mut frames = Vec::new();
+    let mut frame_indices = HashMap::new();
+    let mut profiles = Vec::new();
+    for timeline in Timeline::from_profile(&data) {
+        let frame_of = timeline
+            .intervals
+            .iter()
+            .map(|interval| {
+                *frame_indices
+                    .entry(interval.label.clone())
+                    .or_insert_with(|| {
+                        frames.push(Frame {
+                            name: interval.label.clone(),
+                        });
+                        frames.len() - 1
+                    })
+            })
+            .collect::<Vec<_>>();
+        let events = timeline
+            .boundaries()
+            .into_iter()
+            .map(|boundary| match boundary {
+                Boundary::Begin { interval, at } => Event {
+                    ty: "O",
+                    frame: frame_of[interval],
+                    at,
+                },
+                Boundary::End { interval, at } => Event {
+                    ty: "C",
+                    frame: frame_of[interval],
+                    at,
+                },
+            })
+            .collect::<Vec<_>>();
+        profiles.push(Profile {
+            ty: "evented",
+            name: format!("thread {}", timeline.thread_id),
+            unit: "nanoseconds",
+            start_value: events.first().map_or(0, |e| e.at),
+            end_value: events.last().map_or(0, |e| e.at),
+            events,
+        });
+    }
+
+    Ok(serde_json::to_vec(&File {
+        schema: "https://www.speedscope.app/file-format-schema.json",
+        shared: Shared { frames },
+        profiles,
+        name: title,
+        exporter: "rustc-perf",
+    })?)
+}
@@ -0,0 +1,205 @@
+//! A plain-text table of the time spent per query (or other event label),
+//! like measureme's `summarize` tool prints.
+
+use super::timeline::{Boundary, Timeline};
+use analyzeme::{ProfilingData, Timestamp};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Opt {}
+
+#[derive(Default)]
+struct Row {
+    self_time: u64,
+    time: u64,
+    invocation_count: u32,
+    cache_hits: u32,
+    blocked_time: u64,
+    incremental_load_time: u64,
+}
+
+pub fn generate(pieces: super::Pieces, _: Opt) -> anyhow::Result<Vec<u8>> {
+    let data = ProfilingData::from_buffers(pieces.string_data, pieces.string_index, pieces.events)
+        .map_err(|e| anyhow::format_err!("{:?}", e))?;
+
+    let mut rows: HashMap<String, Row> = HashMap::new();
+    for timeline in Timeline::from_profile(&data) {
+        add_intervals(&mut rows, &timeline);
+    }
+    for event in data.iter() {
+        if let Timestamp::Instant(_) = event.timestamp {
+            let event = event.to_event();
+            if event.event_kind == "QueryCacheHit" {
+                rows.entry(event.label.into_owned()).or_default().cache_hits += 1;
+            }
+        }
+    }
+
+    let total_self_time = rows.values().map(|r| r.self_time).sum::<u64>();
+    let mut rows = rows.into_iter().collect::<Vec<_>>();
+    rows.sort_by(|a, b| b.1.self_time.cmp(&a.1.self_time).then(a.0.cmp(&b.0)));
+
+    let header = [
+        "Item",
+        "Self time",
+        "% of total time",
+        "Time",
+        "Item count",
+        "Cache hits",
+        "Blocked time",
+        "Incremental load time",
+    ];
+    let lines = rows
+        .iter()
+        .map(|(label, row)| {
+            vec![
+                label.clone(),
+                format!("{:.2?}", Duration::from_nanos(row.self_time)),
+                format!(
+                    "{:.3}",
+                    row.self_time as f64 / total_self_time.max(1) as f64 * 100.0
+                ),
+                format!("{:.2?}", Duration::from_nanos(row.time)),
+                row.invocation_count.to_string(),
+                row.cache_hits.to_string(),
+                format!("{:.2?}", Duration::from_nanos(row.blocked_time)),
+                format!("{:.2?}", Duration::from_nanos(row.incremental_load_time)),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let widths = (0..header.len())
+        .map(|i| {
+            lines
+                .iter()
+                .map(|line| line[i].len())
+                .chain(std::iter::once(header[i].len()))
+                .max()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    let header = header.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+    for line in std::iter::once(&header).chain(lines.iter()) {
+        let cells = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>();
+        writeln!(out, "| {} |", cells.join(" | ")).unwrap();
+    }
+    writeln!(
+        out,
+        "\nTotal self time: {:.2?}",
+        Duration::from_nanos(total_self_time)
+    )
+    .unwrap();
+    Ok(out.into_bytes())
+}
+
+/// Adds the times of the timeline's intervals to the rows of their labels.
+fn add_intervals(rows: &mut HashMap<String, Row>, timeline: &Timeline) {
+    // (interval, time spent in its children), innermost last.
+    let mut stack: Vec<(usize, u64)> = Vec::new();
+    for boundary in timeline.boundaries() {
+        match boundary {
+            Boundary::Begin { interval, .. } => stack.push((interval, 0)),
+            Boundary::End { interval, at } => {
+                let (_, children) = stack.pop().unwrap();
+                let interval = &timeline.intervals[interval];
+                let duration = at - interval.start;
+                if let Some((_, parent_children)) = stack.last_mut() {
+                    *parent_children += duration;
+                }
+                let row = rows.entry(interval.label.clone()).or_default();
+                // Waiting for or loading a query's result is tracked
+                // separately from running its provider.
+                match interval.kind.as_str() {
+                    "QueryBlocked" => row.blocked_time += duration,
+                    "IncrementalLoadResult" => row.incremental_load_time += duration,
+                    _ => {
+                        row.self_time += duration.saturating_sub(children);
+                        row.time += duration;
+                        row.invocation_count += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_profile::timeline::Interval;
+
+    fn rows(intervals: &[(&str, &str, u64, u64)]) -> HashMap<String, Row> {
+        let timeline = Timeline {
+            thread_id: 0,
+            intervals: intervals
+                .iter()
+                .map(|&(label, kind, start, end)| Interval {
+                    label: label.to_string(),
+                    kind: kind.to_string(),
+                    start,
+                    end,
+                })
+                .collect(),
+        };
+        let mut rows = HashMap::new();
+        add_intervals(&mut rows, &timeline);
+        rows
+    }
+
+    #[test]
+    fn self_time_excludes_children() {
+        let rows = rows(&[
+            ("typeck", "Query", 0, 100),
+            ("type_of", "Query", 10, 30),
+            ("type_of", "Query", 40, 50),
+            ("mir_built", "Query", 60, 100),
+        ]);
+        let typeck = &rows["typeck"];
+        assert_eq!(typeck.time, 100);
+        assert_eq!(typeck.self_time, 30);
+        assert_eq!(typeck.invocation_count, 1);
+        let type_of = &rows["type_of"];
+        assert_eq!(type_of.time, 30);
+        assert_eq!(type_of.self_time, 30);
+        assert_eq!(type_of.invocation_count, 2);
+        assert_eq!(rows["mir_built"].self_time, 40);
+    }
+
+    #[test]
+    fn nested_children_count_once() {
+        // Only the direct children of an interval are subtracted from its
+        // self time, and a child is cut off at the end of its parent.
+        let rows = rows(&[
+            ("a", "Query", 0, 100),
+            ("b", "Query", 10, 60),
+            ("c", "Query", 20, 80),
+        ]);
+        assert_eq!(rows["a"].self_time, 50);
+        assert_eq!(rows["b"].self_time, 10);
+        assert_eq!(rows["c"].self_time, 40);
+        assert_eq!(rows["c"].time, 40);
+    }
+
+    #[test]
+    fn blocked_and_loading_time_are_separate() {
+        let rows = rows(&[
+            ("typeck", "Query", 0, 100),
+            ("type_of", "QueryBlocked", 10, 30),
+            ("type_of", "IncrementalLoadResult", 40, 45),
+        ]);
+        // The parent still doesn't spend that time itself.
+        assert_eq!(rows["typeck"].self_time, 75);
+        let type_of = &rows["type_of"];
+        assert_eq!(type_of.blocked_time, 20);
+        assert_eq!(type_of.incremental_load_time, 5);
+        assert_eq!(type_of.self_time, 0);
+        assert_eq!(type_of.invocation_count, 0);
+    }
+}
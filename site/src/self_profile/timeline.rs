@@ -0,0 +1,189 @@
+//! The interval events of a profile per thread, ordered for the formats which
+//! describe them as nested begin and end events.
+
+use analyzeme::{ProfilingData, Timestamp};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+pub struct Interval {
+    pub label: String,
+    pub kind: String,
+    /// Nanoseconds since the start of the profile.
+    pub start: u64,
+    pub end: u64,
+}
+
+pub enum Boundary {
+    /// The interval at this index starts, at `at`.
+    Begin { interval: usize, at: u64 },
+    /// The interval at this index ends, at `at`. This is before the end of
+    /// its parent even if the recorded end isn't.
+    End { interval: usize, at: u64 },
+}
+
+pub struct Timeline {
+    pub thread_id: u32,
+    /// Ordered by start, parents before their children.
+    pub intervals: Vec<Interval>,
+}
+
+impl Timeline {
+    /// The timelines of all threads of the profile, ordered by thread id.
+    /// Instant events are left out.
+    pub fn from_profile(data: &ProfilingData) -> Vec<Timeline> {
+        let start_time = data.metadata.start_time;
+        let mut by_thread: BTreeMap<u32, Vec<Interval>> = BTreeMap::new();
+        for event in data.iter() {
+            let (start, end) = match event.timestamp {
+                Timestamp::Interval { start, end } => (start, end),
+                Timestamp::Instant(_) => continue,
+            };
+            let start = nanos_since(start_time, start);
+            let end = nanos_since(start_time, end).max(start);
+            let event = event.to_event();
+            by_thread
+                .entry(event.thread_id)
+                .or_default()
+                .push(Interval {
+                    label: event.label.into_owned(),
+                    kind: event.event_kind.into_owned(),
+                    start,
+                    end,
+                });
+        }
+        by_thread
+            .into_iter()
+            .map(|(thread_id, mut intervals)| {
+                intervals.sort_by_key(|i| (i.start, std::cmp::Reverse(i.end)));
+                Timeline {
+                    thread_id,
+                    intervals,
+                }
+            })
+            .collect()
+    }
+
+    /// The beginnings and ends of the intervals, in time order.
+    pub fn boundaries(&self) -> Vec<Boundary> {
+        let mut boundaries = Vec::with_capacity(2 * self.intervals.len());
+        // (interval, end), innermost last.
+        let mut open: Vec<(usize, u64)> = Vec::new();
+        for (idx, interval) in self.intervals.iter().enumerate() {
+            while let Some(&(top, end)) = open.last() {
+                if end > interval.start {
+                    break;
+                }
+                boundaries.push(Boundary::End {
+                    interval: top,
+                    at: end,
+                });
+                open.pop();
+            }
+            boundaries.push(Boundary::Begin {
+                interval: idx,
+                at: interval.start,
+            });
+            let end = match open.last() {
+                Some(&(_, parent_end)) => interval.end.min(parent_end),
+                None => interval.end,
+            };
+            open.push((idx, end));
+        }
+        while let Some((top, end)) = open.pop() {
+            boundaries.push(Boundary::End {
+                interval: top,
+                at: end,
+            });
+        }
+        boundaries
+    }
+}
+
+fn nanos_since(start: SystemTime, time: SystemTime) -> u64 {
+    time.duration_since(start).unwrap_or_default().as_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeline(intervals: &[(u64, u64)]) -> Timeline {
+        Timeline {
+            thread_id: 0,
+            intervals: intervals
+                .iter()
+                .map(|&(start, end)| Interval {
+                    label: String::new(),
+                    kind: String::new(),
+                    start,
+                    end,
+                })
+                .collect(),
+        }
+    }
+
+    /// (is_begin, interval, at)
+    fn boundaries(timeline: &Timeline) -> Vec<(bool, usize, u64)> {
+        timeline
+            .boundaries()
+            .into_iter()
+            .map(|b| match b {
+                Boundary::Begin { interval, at } => (true, interval, at),
+                Boundary::End { interval, at } => (false, interval, at),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nested_and_sequential() {
+        let timeline = timeline(&[(0, 10), (1, 4), (4, 6), (12, 15)]);
+        assert_eq!(
+            boundaries(&timeline),
+            vec![
+                (true, 0, 0),
+                (true, 1, 1),
+                (false, 1, 4),
+                (true, 2, 4),
+                (false, 2, 6),
+                (false, 0, 10),
+                (true, 3, 12),
+                (false, 3, 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_child_ends_with_parent() {
+        // The second interval starts within the first but ends after it.
+        let timeline = timeline(&[(0, 10), (5, 20), (7, 8)]);
+        assert_eq!(
+            boundaries(&timeline),
+            vec![
+                (true, 0, 0),
+                (true, 1, 5),
+                (true, 2, 7),
+                (false, 2, 8),
+                (false, 1, 10),
+                (false, 0, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_intervals_end_in_order() {
+        // Nothing follows the intervals, so they are all still open once the
+        // last one has begun.
+        let timeline = timeline(&[(0, 30), (10, 20), (15, 15)]);
+        assert_eq!(
+            boundaries(&timeline),
+            vec![
+                (true, 0, 0),
+                (true, 1, 10),
+                (true, 2, 15),
+                (false, 2, 15),
+                (false, 1, 20),
+                (false, 0, 30),
+            ]
+        );
+    }
+}
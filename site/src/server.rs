@@ -8,14 +8,15 @@
 // except according to those terms.
 
 use bytes::buf::BufExt;
+use chrono::{DateTime, Utc};
 use parking_lot::Mutex;
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::convert::TryInto;
 use std::fmt;
 use std::fs;
 use std::io::Read;
 use std::net::SocketAddr;
+use std::ops::Range;
 use std::path::Path;
 use std::str;
 use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
@@ -29,6 +30,7 @@ use headers::Header;
 use headers::{Authorization, ContentType};
 use hyper::StatusCode;
 use log::{debug, error, info};
+use regex::Regex;
 use ring::hmac;
 use rmp_serde;
 use serde::de::DeserializeOwned;
@@ -39,9 +41,12 @@ type Request = http::Request<hyper::Body>;
 type Response = http::Response<hyper::Body>;
 
 pub use crate::api::{
-    self, bootstrap, dashboard, data, days, github, graph, info, self_profile, self_profile_raw,
-    status, CommitResponse, DateData, ServerResult, StyledBenchmarkName,
+    self, aggregate_indices, annotate, bootstrap, change_points, compare_many, dashboard, data,
+    days, delete_annotation, error_history, github, graph, info, invalidate, query, queue_artifact,
+    self_profile, self_profile_raw, status, CommitResponse, DateData, ServerResult,
+    StyledBenchmarkName,
 };
+use crate::comparison::{self, StatSummary};
 use crate::db::{self, Cache, Crate, Profile};
 use crate::interpolate::Interpolated;
 use crate::load::{Config, InputData};
@@ -231,6 +236,27 @@ pub async fn handle_dashboard(data: Arc<InputData>) -> ServerResult<dashboard::R
     .await
     .unwrap();
 
+    let conn = data.conn().await;
+    let db_index = data.index.load();
+    let mut indices = Vec::new();
+    for index in &data.config.aggregate_indices {
+        let recorded = conn.aggregate_index(&index.name).await;
+        // Not `artifact_id`, which would create the versions which haven't
+        // been benchmarked.
+        let values = cids
+            .iter()
+            .map(|cid| {
+                cid.lookup(&db_index)
+                    .and_then(|aid| recorded.get(&aid).copied().flatten())
+            })
+            .collect();
+        indices.push(dashboard::AggregateIndex {
+            name: index.name.clone(),
+            baseline: index.baseline.clone(),
+            values,
+        });
+    }
+
     Ok(dashboard::Response {
         versions: cids
             .iter()
@@ -242,9 +268,42 @@ pub async fn handle_dashboard(data: Arc<InputData>) -> ServerResult<dashboard::R
         check: by_profile.check,
         debug: by_profile.debug,
         opt: by_profile.opt,
+        indices,
     })
 }
 
+pub async fn handle_aggregate_indices(
+    data: Arc<InputData>,
+) -> ServerResult<aggregate_indices::Response> {
+    let artifacts = crate::aggregate::artifacts(&data);
+    let conn = data.conn().await;
+    let db_index = data.index.load();
+    let mut indices = Vec::new();
+    for index in &data.config.aggregate_indices {
+        let recorded = conn.aggregate_index(&index.name).await;
+        let mut values = Vec::new();
+        for artifact in &artifacts {
+            let value = artifact
+                .lookup(&db_index)
+                .and_then(|aid| recorded.get(&aid).copied().flatten());
+            if let Some(value) = value {
+                let name = match artifact {
+                    ArtifactId::Commit(c) => c.sha.clone(),
+                    ArtifactId::Artifact(a) => a.clone(),
+                };
+                values.push((name, value));
+            }
+        }
+        indices.push(aggregate_indices::AggregateIndex {
+            name: index.name.clone(),
+            baseline: index.baseline.clone(),
+            stat: index.stat.clone(),
+            values,
+        });
+    }
+    Ok(aggregate_indices::Response { indices })
+}
+
 fn prettify_log(log: &str) -> Option<String> {
     let mut lines = log.lines();
     let first = lines.next()?;
@@ -258,9 +317,11 @@ pub async fn handle_status_page(data: Arc<InputData>) -> status::Response {
     let last_commit = idx.commits().last().cloned();
 
     let missing = data.missing_commits().await;
-    // FIXME: no current builds
     let conn = data.conn().await;
-    let current = if let Some(artifact) = conn.in_progress_artifacts().await.pop() {
+    let leases = conn.leases().await;
+    let now = Utc::now();
+    let mut current = Vec::new();
+    for artifact in conn.in_progress_artifacts().await {
         let steps = conn
             .in_progress_steps(&artifact)
             .await
@@ -273,13 +334,15 @@ pub async fn handle_status_page(data: Arc<InputData>) -> status::Response {
             })
             .collect();
 
-        Some(crate::api::status::CurrentState {
+        let lease = leases.iter().find(|l| l.artifact == artifact.name());
+        current.push(crate::api::status::CurrentState {
+            collector: lease.map(|l| l.collector.clone()),
+            last_heartbeat: lease.map(|l| l.heartbeat.timestamp()),
+            lease_expired: lease.map_or(true, |l| lease_expired(l, now)),
             artifact,
             progress: steps,
-        })
-    } else {
-        None
-    };
+        });
+    }
 
     let errors = if let Some(last) = &last_commit {
         data.conn()
@@ -313,47 +376,243 @@ pub async fn handle_status_page(data: Arc<InputData>) -> status::Response {
         benchmarks: benchmark_state,
         missing,
         current,
+        failed_artifacts: conn
+            .failed_artifacts()
+            .await
+            .into_iter()
+            .map(|a| status::FailedArtifact {
+                name: a.name,
+                error: a.error,
+            })
+            .collect(),
         most_recent_end: conn.last_end_time().await.map(|d| d.timestamp()),
     }
 }
 
-pub async fn handle_next_commit(data: Arc<InputData>) -> collector::api::next_commit::Response {
-    let commit = data.missing_commits().await.into_iter().next().map(|c| {
-        let (include, exclude, runs) = match c.1 {
-            crate::load::MissingReason::Try {
-                include,
-                exclude,
-                runs,
-                ..
-            } => (include, exclude, runs),
-            crate::load::MissingReason::InProgress(Some(previous)) => {
-                if let crate::load::MissingReason::Try {
-                    include,
-                    exclude,
-                    runs,
-                    ..
-                } = *previous
-                {
-                    (include, exclude, runs)
-                } else {
-                    (None, None, None)
-                }
-            }
-            _ => (None, None, None),
+/// Reduces an error log to the line which best identifies the failure, with
+/// paths, line numbers and hashes removed.
+fn error_signature(error: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref PATH: Regex = Regex::new(r"(?:[\w.-]*/)+([\w.-]+)").unwrap();
+        static ref LOCATION: Regex = Regex::new(r":\d+(?::\d+)?").unwrap();
+        static ref HASH: Regex = Regex::new(r"\b[0-9a-f]{7,}\b").unwrap();
+    }
+    let is_cause = |l: &&str| {
+        (l.starts_with("error")
+            && !l.starts_with("error: could not compile")
+            && !l.starts_with("error: aborting"))
+            || l.contains("panicked at")
+    };
+    let line = error
+        .lines()
+        .map(str::trim)
+        .find(is_cause)
+        .or_else(|| error.lines().map(str::trim).find(|l| !l.is_empty()))
+        .unwrap_or("");
+    let line = PATH.replace_all(line, "$1");
+    let line = LOCATION.replace_all(&line, "");
+    HASH.replace_all(&line, "<hash>").into_owned()
+}
+
+pub async fn handle_error_history(data: Arc<InputData>) -> error_history::Response {
+    let master_commits = data.master_commits().await;
+    // Artifacts still being benchmarked have incomplete results, so a
+    // benchmark which hasn't run yet would look like it recovered.
+    let in_progress = data.conn().await.in_progress_artifacts().await;
+    let idx = data.index.load();
+    let commits = idx
+        .commits()
+        .into_iter()
+        .filter(|c| !c.is_try() && !in_progress.contains(&ArtifactId::from(c.clone())))
+        .collect::<Vec<_>>();
+    let aids = commits
+        .iter()
+        .map(|c| ArtifactId::from(c.clone()).lookup(&idx).unwrap())
+        .collect::<Vec<_>>();
+    // A benchmark without an error passed if it has results, which tells it
+    // apart from commits where it wasn't run at all.
+    let results = data
+        .query::<Option<f64>>(
+            selector::Query::new()
+                .set::<String>(selector::Tag::Crate, selector::Selector::All)
+                .set::<String>(selector::Tag::Profile, selector::Selector::All)
+                .set::<String>(selector::Tag::Cache, selector::Selector::All)
+                .set(
+                    selector::Tag::ProcessStatistic,
+                    selector::Selector::One(String::from("instructions:u")),
+                ),
+            Arc::new(commits.iter().cloned().map(ArtifactId::from).collect()),
+        )
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("failed to load results for the error history: {}", e);
+            Vec::new()
+        });
+    let conn = data.conn().await;
+    // Sources are recorded whenever a benchmark is run, whether or not it
+    // fails, which also covers benchmarks failing before any results. The
+    // rustc benchmark has no sources of its own, but records its crate
+    // timings instead.
+    let mut ran = conn
+        .get_benchmark_sources(&aids)
+        .await
+        .into_iter()
+        .map(|(name, sources)| (name, sources.iter().map(Option::is_some).collect()))
+        .collect::<HashMap<_, Vec<bool>>>();
+    let bootstrap = conn.get_bootstrap(&aids).await;
+    ran.insert(
+        String::from("rustc"),
+        (0..aids.len())
+            .map(|i| bootstrap.values().any(|times| times[i].is_some()))
+            .collect(),
+    );
+    for sr in results {
+        let name = match sr.path.get::<Crate>() {
+            Ok(krate) => krate.to_string(),
+            Err(_) => continue,
+        };
+        let ran = ran.entry(name).or_insert_with(|| vec![false; aids.len()]);
+        for (i, (_, value)) in sr.series.enumerate() {
+            ran[i] |= value.is_some();
+        }
+    }
+
+    let mut benchmarks = Vec::new();
+    for (name, errors) in conn.get_errors(&aids).await {
+        let ran = ran.get(&name);
+        let passed = |i: usize| errors[i].is_none() && ran.map_or(false, |ran| ran[i]);
+        // Only benchmarks which failed at least once are returned.
+        let last = errors.iter().rposition(|e| e.is_some()).unwrap();
+        let first = (0..last).rev().find(|&i| passed(i)).map_or(0, |i| i + 1);
+        let first = (first..=last).find(|&i| errors[i].is_some()).unwrap();
+        let error = errors[first].clone().unwrap();
+        let error = prettify_log(&error).unwrap_or(error);
+        let sha = &commits[first].sha;
+        let pr = match master_commits.iter().find(|c| &c.sha == sha) {
+            Some(c) => c.pr,
+            None => conn.pr_of(sha).await,
         };
-        collector::api::next_commit::Commit {
-            sha: c.0.sha,
+        benchmarks.push(error_history::BenchmarkErrors {
+            name,
+            first_failure: commits[first].clone(),
+            pr,
+            recovered: (last + 1..commits.len())
+                .find(|&i| passed(i))
+                .map(|i| commits[i].clone()),
+            failures: errors[first..=last].iter().filter(|e| e.is_some()).count(),
+            signature: error_signature(&error),
+            error,
+        });
+    }
+    benchmarks.sort_by(|a, b| {
+        a.recovered
+            .is_some()
+            .cmp(&b.recovered.is_some())
+            .then_with(|| b.first_failure.date.cmp(&a.first_failure.date))
+    });
+
+    error_history::Response { benchmarks }
+}
+
+/// How long a collector may go without a heartbeat before the artifact it is
+/// benchmarking is handed to another collector.
+const LEASE_EXPIRY: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+fn lease_expired(lease: &database::CollectorLease, now: DateTime<Utc>) -> bool {
+    now.signed_duration_since(lease.heartbeat)
+        .to_std()
+        .unwrap_or_default()
+        > LEASE_EXPIRY
+}
+
+/// Hands `collector` the first missing commit which no other live collector
+/// is benchmarking, and leases it to `collector`.
+pub async fn handle_next_commit(
+    data: Arc<InputData>,
+    collector: String,
+) -> collector::api::next_commit::Response {
+    let mut next = None;
+    {
+        let conn = data.conn().await;
+        for c in data.missing_commits().await {
+            if conn
+                .acquire_lease(&collector, c.0.name(), LEASE_EXPIRY)
+                .await
+            {
+                next = Some(c);
+                break;
+            }
+        }
+    }
+    let (aid, reason) = match next {
+        Some((aid, crate::load::MissingReason::InProgress(Some(previous)))) => (aid, *previous),
+        Some(next) => next,
+        None => {
+            return collector::api::next_commit::Response {
+                commit: None,
+                artifact: None,
+            }
+        }
+    };
+    let sha = match aid {
+        ArtifactId::Commit(c) => c.sha,
+        ArtifactId::Artifact(name) => {
+            let local_rustc = match reason {
+                crate::load::MissingReason::Artifact { local_rustc } => local_rustc,
+                _ => None,
+            };
+            return collector::api::next_commit::Response {
+                commit: None,
+                artifact: Some(collector::api::next_commit::Artifact { name, local_rustc }),
+            };
+        }
+    };
+    let commit = match reason {
+        crate::load::MissingReason::Try {
             include,
             exclude,
             runs,
-        }
-    });
+            builds,
+            scenarios,
+            ..
+        } => collector::api::next_commit::Commit {
+            sha,
+            include,
+            exclude,
+            runs,
+            builds,
+            scenarios,
+        },
+        _ => collector::api::next_commit::Commit {
+            sha,
+            include: None,
+            exclude: None,
+            runs: None,
+            builds: None,
+            scenarios: None,
+        },
+    };
+
+    collector::api::next_commit::Response {
+        commit: Some(commit),
+        artifact: None,
+    }
+}
 
-    collector::api::next_commit::Response { commit }
+pub async fn handle_collector_heartbeat(
+    request: collector::api::collector_heartbeat::Request,
+    data: &InputData,
+) -> ServerResult<collector::api::collector_heartbeat::Response> {
+    let conn = data.conn().await;
+    let lease_held = conn.renew_lease(&request.collector, &request.sha).await;
+    if request.finished {
+        conn.release_lease(&request.collector, &request.sha).await;
+    }
+    Ok(collector::api::collector_heartbeat::Response { lease_held })
 }
 
 struct CommitIdxCache {
-    commit_idx: RefCell<HashMap<String, u16>>,
+    commit_idx: RefCell<HashMap<String, u32>>,
     commits: RefCell<Vec<String>>,
 }
 
@@ -369,7 +628,7 @@ impl CommitIdxCache {
         std::mem::take(&mut *self.commits.borrow_mut())
     }
 
-    fn lookup(&self, commit: String) -> u16 {
+    fn lookup(&self, commit: String) -> u32 {
         *self
             .commit_idx
             .borrow_mut()
@@ -377,9 +636,7 @@ impl CommitIdxCache {
             .or_insert_with(|| {
                 let idx = self.commits.borrow().len();
                 self.commits.borrow_mut().push(commit);
-                idx.try_into().unwrap_or_else(|_| {
-                    panic!("{} too big", idx);
-                })
+                idx as u32
             })
     }
 }
@@ -422,6 +679,7 @@ pub async fn handle_graph_new(
     log::info!("handle_graph_new({:?})", body);
     let range = data.data_range(body.start.clone()..=body.end.clone());
     let commits: Arc<Vec<ArtifactId>> = Arc::new(range.iter().map(|c| c.clone().into()).collect());
+    let periods = periods(&commits, body.resolution);
 
     let mut benchmarks = HashMap::new();
 
@@ -436,13 +694,42 @@ pub async fn handle_graph_new(
             for (name, points) in series.iter() {
                 let mut series = graph::Series {
                     points: Vec::new(),
+                    min: Vec::new(),
+                    max: Vec::new(),
                     is_interpolated: Default::default(),
                 };
 
-                for (idx, point) in points.iter().enumerate() {
-                    series.points.push(point.y);
-                    if point.is_interpolated {
-                        series.is_interpolated.insert(idx as u16);
+                if let Some(periods) = &periods {
+                    for (idx, (_, range)) in periods.iter().enumerate() {
+                        let points = &points[range.clone()];
+                        // Interpolated points only stand in for missing data,
+                        // so they are used only if the period has nothing else.
+                        let interpolated = points.iter().all(|p| p.is_interpolated);
+                        // Percentages relative to a first value of zero aren't
+                        // finite, and can't be ordered.
+                        let mut ys = points
+                            .iter()
+                            .filter(|p| interpolated || !p.is_interpolated)
+                            .map(|p| p.y)
+                            .filter(|y| y.is_finite())
+                            .collect::<Vec<_>>();
+                        ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                        if ys.is_empty() {
+                            ys.push(f32::NAN);
+                        }
+                        series.points.push(ys[ys.len() / 2]);
+                        series.min.push(ys[0]);
+                        series.max.push(ys[ys.len() - 1]);
+                        if interpolated {
+                            series.is_interpolated.insert(idx as u32);
+                        }
+                    }
+                } else {
+                    for (idx, point) in points.iter().enumerate() {
+                        series.points.push(point.y);
+                        if point.is_interpolated {
+                            series.is_interpolated.insert(idx as u32);
+                        }
                     }
                 }
 
@@ -455,18 +742,107 @@ pub async fn handle_graph_new(
         benchmarks.insert(crate_.clone(), by_profile);
     }
 
-    Ok(Arc::new(graph::NewResponse {
-        commits: commits
+    let (positions, aids): (Vec<usize>, Vec<_>) = {
+        let index = data.index.load();
+        commits
             .iter()
-            .map(|c| match c {
-                ArtifactId::Commit(c) => (c.date.0.timestamp(), c.sha.clone()),
-                ArtifactId::Artifact(_) => unreachable!(),
-            })
-            .collect(),
+            .enumerate()
+            .filter_map(|(idx, c)| Some((idx, c.lookup(&index)?)))
+            .unzip()
+    };
+    let source_changes = data
+        .conn()
+        .await
+        .get_benchmark_sources(&aids)
+        .await
+        .into_iter()
+        .map(|(krate, hashes)| {
+            let mut changes = source_changes(&hashes)
+                .into_iter()
+                .map(|idx| positions[idx])
+                .map(|position| match &periods {
+                    Some(periods) => periods
+                        .iter()
+                        .position(|(_, range)| range.contains(&position))
+                        .unwrap() as u32,
+                    None => position as u32,
+                })
+                .collect::<Vec<_>>();
+            changes.dedup();
+            (krate, changes)
+        })
+        .collect();
+
+    let commits = commits.iter().map(|c| match c {
+        ArtifactId::Commit(c) => (c.date.0.timestamp(), c.sha.clone()),
+        ArtifactId::Artifact(_) => unreachable!(),
+    });
+    let commits = match &periods {
+        Some(periods) => {
+            let commits = commits.collect::<Vec<_>>();
+            periods
+                .iter()
+                .map(|(start, range)| (*start, commits[range.end - 1].1.clone()))
+                .collect()
+        }
+        None => commits.collect(),
+    };
+
+    Ok(Arc::new(graph::NewResponse {
+        commits,
         benchmarks,
+        source_changes,
+        annotations: raw.annotations.clone(),
     }))
 }
 
+/// Groups consecutive commits into UTC days or weeks (starting on Monday),
+/// returning the start of each period (in seconds since the epoch) and the
+/// positions of the commits within it. Returns `None` when every commit is its
+/// own point.
+fn periods(
+    commits: &[ArtifactId],
+    resolution: graph::Resolution,
+) -> Option<Vec<(i64, Range<usize>)>> {
+    let length = match resolution {
+        graph::Resolution::Commit => return None,
+        graph::Resolution::Day => 24 * 60 * 60,
+        graph::Resolution::Week => 7 * 24 * 60 * 60,
+    };
+    let mut periods: Vec<(i64, Range<usize>)> = Vec::new();
+    for (idx, commit) in commits.iter().enumerate() {
+        let timestamp = match commit {
+            ArtifactId::Commit(c) => c.date.0.timestamp(),
+            ArtifactId::Artifact(_) => unreachable!(),
+        };
+        // The epoch was a Thursday, so weeks are aligned to the Monday before.
+        // This is a whole number of days, which leaves days unaffected.
+        let offset = 3 * 24 * 60 * 60;
+        let start = (timestamp + offset).div_euclid(length) * length - offset;
+        match periods.last_mut() {
+            Some((last, range)) if *last == start => range.end = idx + 1,
+            _ => periods.push((start, idx..idx + 1)),
+        }
+    }
+    Some(periods)
+}
+
+/// Returns the positions at which the benchmark source hash differs from the
+/// closest preceding recorded hash.
+fn source_changes(hashes: &[Option<String>]) -> Vec<usize> {
+    let mut previous: Option<&str> = None;
+    let mut changes = Vec::new();
+    for (idx, hash) in hashes.iter().enumerate() {
+        if let Some(hash) = hash {
+            if previous.map_or(false, |p| p != hash) {
+                changes.push(idx);
+            }
+            previous = Some(hash);
+        }
+    }
+    changes
+}
+
 pub async fn handle_graph(
     body: graph::Request,
     data: &InputData,
@@ -478,6 +854,7 @@ pub async fn handle_graph(
             end: Bound::None,
             stat: String::from("instructions:u"),
             absolute: true,
+            resolution: body.resolution,
         };
 
     if is_default_query {
@@ -615,11 +992,17 @@ pub async fn handle_graph(
             .push((sr.path.get::<Cache>()?.to_string(), sr.series));
     }
 
+    let annotations = match (commits.first(), commits.last()) {
+        (Some(first), Some(last)) => data.annotations_between(first, last).await,
+        _ => Vec::new(),
+    };
+
     let resp = Arc::new(graph::Response {
         max: by_krate_max,
         benchmarks: by_krate,
         colors: vec![String::new(), String::from(INTERPOLATED_COLOR)],
         commits: cc.into_commits(),
+        annotations,
     });
 
     if is_default_query {
@@ -629,6 +1012,256 @@ pub async fn handle_graph(
     Ok(resp)
 }
 
+/// Finds the level shifts in every benchmark's results over a range of master
+/// commits, and attributes them to the commit at which they begin.
+pub async fn handle_change_points(
+    body: change_points::Request,
+    data: &InputData,
+) -> ServerResult<change_points::Response> {
+    log::info!("handle_change_points({:?})", body);
+    let min_percent = body.min_percent.unwrap_or(1.0);
+    let commits = data
+        .data_range(body.start.clone()..=body.end.clone())
+        .into_iter()
+        .filter(|c| !c.is_try())
+        .map(ArtifactId::from)
+        .collect::<Vec<_>>();
+    let commits = Arc::new(commits);
+
+    let series = data
+        .query::<Option<f64>>(
+            selector::Query::new()
+                .set::<String>(selector::Tag::Crate, selector::Selector::All)
+                .set::<String>(selector::Tag::Profile, selector::Selector::All)
+                .set::<String>(selector::Tag::Cache, selector::Selector::All)
+                .set(
+                    selector::Tag::ProcessStatistic,
+                    selector::Selector::One(body.stat.clone()),
+                ),
+            commits.clone(),
+        )
+        .await?;
+    let annotations = data.conn().await.annotations().await;
+
+    let mut changes = Vec::new();
+    for sr in series {
+        let benchmark = sr.path.get::<Crate>()?.to_string();
+        let profile = sr.path.get::<Profile>()?.to_string();
+        let cache = sr.path.get::<Cache>()?.to_string();
+        // Not interpolated, as filled in points would attribute shifts to
+        // commits where the benchmark didn't run.
+        let (aids, values): (Vec<_>, Vec<_>) = sr
+            .series
+            .filter_map(|(aid, point)| Some((aid, point.filter(|v| v.is_finite())?)))
+            .unzip();
+        for shift in crate::changepoint::detect(&values) {
+            if shift.before == 0.0 || shift.percent().abs() < min_percent {
+                continue;
+            }
+            // Annotated changes are known, e.g. to be noise.
+            let (commit, end_commit) = (&aids[shift.index], &aids[shift.end]);
+            if annotations.iter().any(|annotation| {
+                annotation.applies_to(&benchmark, &profile, &cache)
+                    && data.annotation_overlaps(annotation, commit, end_commit)
+            }) {
+                continue;
+            }
+            changes.push(change_points::ChangePoint {
+                benchmark: benchmark.clone(),
+                profile: profile.clone(),
+                cache: cache.clone(),
+                commit: commit.name().to_string(),
+                pr: None,
+                end_commit: end_commit.name().to_string(),
+                before: shift.before,
+                after: shift.after,
+                percent: shift.percent(),
+            });
+        }
+    }
+
+    let master_commits = data.master_commits().await;
+    let conn = data.conn().await;
+    for change in &mut changes {
+        change.pr = match master_commits.iter().find(|c| c.sha == change.commit) {
+            Some(c) => c.pr,
+            None => conn.pr_of(&change.commit).await,
+        };
+    }
+    changes.sort_by(|a, b| b.percent.abs().partial_cmp(&a.percent.abs()).unwrap());
+
+    Ok(change_points::Response { changes })
+}
+
+pub async fn handle_compare_many(
+    body: compare_many::Request,
+    data: &InputData,
+) -> ServerResult<compare_many::Response> {
+    log::info!("handle_compare_many({:?})", body);
+    if body.artifacts.is_empty() {
+        return Err(String::from("no artifacts to compare against the baseline"));
+    }
+    let baseline = data.data_for(true, body.baseline.clone()).ok_or(format!(
+        "could not find baseline for bound {:?}",
+        body.baseline
+    ))?;
+    let mut cids = vec![baseline.clone()];
+    // Resolve the artifacts like the baseline, so that a date picks the same
+    // commit in either position.
+    for bound in &body.artifacts {
+        cids.push(
+            data.data_for(true, bound.clone())
+                .ok_or(format!("could not find artifact for bound {:?}", bound))?,
+        );
+    }
+    let cids = Arc::new(cids);
+
+    let query = selector::Query::new()
+        .set::<String>(Tag::Crate, selector::Selector::All)
+        .set::<String>(Tag::Profile, selector::Selector::All)
+        .set::<String>(Tag::Cache, selector::Selector::All)
+        .set(
+            Tag::ProcessStatistic,
+            selector::Selector::One(body.stat.clone()),
+        );
+    let responses = data.query::<Option<f64>>(query, cids.clone()).await?;
+
+    let mut rows = Vec::new();
+    for response in responses {
+        let mut points = response.series.map(|(_, point)| point);
+        let base = points.next().unwrap();
+        let values = points.collect::<Vec<_>>();
+        if base.is_none() && values.iter().all(Option::is_none) {
+            continue;
+        }
+        let percents = values
+            .iter()
+            .map(|value| match (base, *value) {
+                (Some(base), Some(value)) if base != 0.0 => Some((value - base) / base * 100.0),
+                _ => None,
+            })
+            .collect();
+        rows.push(compare_many::Row {
+            benchmark: response.path.get::<Crate>()?.to_string(),
+            profile: response.path.get::<Profile>()?.to_string(),
+            cache: response.path.get::<Cache>()?.to_string(),
+            baseline: base,
+            values,
+            percents,
+        });
+    }
+    rows.sort_by(|a, b| {
+        (&a.benchmark, &a.profile, &a.cache).cmp(&(&b.benchmark, &b.profile, &b.cache))
+    });
+
+    Ok(compare_many::Response {
+        baseline: baseline.name().to_string(),
+        artifacts: cids[1..].iter().map(|a| a.name().to_string()).collect(),
+        rows,
+    })
+}
+
+/// The most values `handle_query` returns; larger queries have to be split.
+const MAX_QUERY_VALUES: usize = 1_000_000;
+
+pub async fn handle_query(body: query::Request, data: &InputData) -> ServerResult<query::Response> {
+    log::info!("handle_query({:?})", body);
+    fn selector(filter: Option<query::Filter>) -> selector::Selector<String> {
+        match filter {
+            None => selector::Selector::All,
+            Some(query::Filter::One(one)) => selector::Selector::One(one),
+            Some(query::Filter::Subset(subset)) => selector::Selector::Subset(subset),
+        }
+    }
+
+    let artifacts = data
+        .data_range(body.start.clone()..=body.end.clone())
+        .into_iter()
+        .map(ArtifactId::from)
+        .collect::<Vec<_>>();
+    // Statistics and self-profile queries are separate kinds of series.
+    let (tag, filter) = match (body.stat, body.query) {
+        (stat, None) => (Tag::ProcessStatistic, stat),
+        (None, Some(query)) => (Tag::QueryLabel, Some(query)),
+        (Some(_), Some(_)) => return Err(String::from("`stat` and `query` can't both be set")),
+    };
+    let q = selector::Query::new()
+        .set(Tag::Crate, selector(body.benchmark))
+        .set(Tag::Profile, selector(body.profile))
+        .set(Tag::Cache, selector(body.cache))
+        .set(tag, selector(filter));
+
+    // Checked before loading anything, as loading is what's expensive.
+    let values = data.count_series(q.clone())? * artifacts.len();
+    if values > MAX_QUERY_VALUES {
+        return Err(format!(
+            "the query selects {} values, more than the limit of {}; \
+            select fewer series or a shorter range",
+            values, MAX_QUERY_VALUES
+        ));
+    }
+    let series = data
+        .query::<Option<f64>>(q, Arc::new(artifacts.clone()))
+        .await?;
+
+    let series = series
+        .into_iter()
+        .map(|sr| {
+            Ok(query::Series {
+                benchmark: sr.path.get::<Crate>()?.to_string(),
+                profile: sr.path.get::<Profile>()?.to_string(),
+                cache: sr.path.get::<Cache>()?.to_string(),
+                stat: sr
+                    .path
+                    .get::<database::ProcessStatistic>()
+                    .ok()
+                    .map(|s| s.to_string()),
+                query: sr
+                    .path
+                    .get::<database::QueryLabel>()
+                    .ok()
+                    .map(|q| q.to_string()),
+                values: sr.series.map(|(_, value)| value).collect(),
+            })
+        })
+        .collect::<ServerResult<Vec<_>>>()?;
+
+    Ok(query::Response {
+        artifacts: artifacts.iter().map(|a| a.name().to_string()).collect(),
+        series,
+    })
+}
+
+/// Formats the series as CSV, with one row per recorded value.
+fn query_csv(response: &query::Response) -> String {
+    fn field(value: &str) -> String {
+        if value.contains(&[',', '"', '\n'][..]) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    let mut csv = String::from("artifact,benchmark,profile,cache,stat,query,value\n");
+    for series in &response.series {
+        for (artifact, value) in response.artifacts.iter().zip(&series.values) {
+            if let Some(value) = value {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{}\n",
+                    field(artifact),
+                    field(&series.benchmark),
+                    field(&series.profile),
+                    field(&series.cache),
+                    field(series.stat.as_deref().unwrap_or("")),
+                    field(series.query.as_deref().unwrap_or("")),
+                    value
+                ));
+            }
+        }
+    }
+    csv
+}
+
 pub async fn handle_compare(body: days::Request, data: &InputData) -> ServerResult<days::Response> {
     log::info!("handle_compare({:?})", body);
     let a = data.data_for(true, body.start.clone()).ok_or(format!(
@@ -641,21 +1274,33 @@ pub async fn handle_compare(body: days::Request, data: &InputData) -> ServerResu
     ))?;
     let cids = Arc::new(vec![a.clone().into(), b.clone().into()]);
 
+    let mut stats = vec![body.stat.clone()];
+    for stat in &body.stats {
+        if !stats.contains(stat) {
+            stats.push(stat.clone());
+        }
+    }
     let query = selector::Query::new()
         .set::<String>(Tag::Crate, selector::Selector::All)
         .set::<String>(Tag::Cache, selector::Selector::All)
         .set::<String>(Tag::Profile, selector::Selector::All)
         .set(
             Tag::ProcessStatistic,
-            selector::Selector::One(body.stat.clone()),
+            selector::Selector::Subset(stats.clone()),
         );
 
     let (responses, commits) = futures::join!(
         data.query::<Option<f64>>(query, cids),
-        rustc_artifacts::master_commits(),
+        data.master_commits(),
     );
-    let commits = commits.map_err(|e| e.to_string())?;
-    let mut responses = responses?;
+    let mut by_stat = HashMap::new();
+    for response in responses? {
+        let stat = response
+            .path
+            .get::<database::ProcessStatistic>()?
+            .to_string();
+        by_stat.entry(stat).or_insert_with(Vec::new).push(response);
+    }
 
     let conn = data.conn().await;
     let prev = match &a {
@@ -675,6 +1320,18 @@ pub async fn handle_compare(body: days::Request, data: &InputData) -> ServerResu
         }
         _ => false,
     };
+    let source_changed = {
+        let aids = [conn.artifact_id(&a).await, conn.artifact_id(&b).await];
+        let mut changed = conn
+            .get_benchmark_sources(&aids)
+            .await
+            .into_iter()
+            .filter(|(_, hashes)| !source_changes(hashes).is_empty())
+            .map(|(krate, _)| krate)
+            .collect::<Vec<_>>();
+        changed.sort();
+        changed
+    };
     let next = match &b {
         ArtifactId::Commit(b) => commits
             .iter()
@@ -682,24 +1339,54 @@ pub async fn handle_compare(body: days::Request, data: &InputData) -> ServerResu
             .map(|c| c.sha.clone()),
         ArtifactId::Artifact(_) => None,
     };
+    let annotations = data.annotations_between(&a, &b).await;
+
+    // (statistic, values of `a`, values of `b`), starting with `body.stat`.
+    let mut values = Vec::new();
+    for stat in stats {
+        let mut series = by_stat.remove(&stat).unwrap_or_default();
+        let a_values = DateData::consume_values(&a, &mut series);
+        let b_values = DateData::consume_values(&b, &mut series);
+        values.push((stat, a_values, b_values));
+    }
+    let summaries = values
+        .iter()
+        .map(|(stat, a, b)| StatSummary::new(stat, a, b, &source_changed, &annotations))
+        .collect::<Vec<_>>();
+    let query_changes = if body.self_profile {
+        comparison::query_changes(data, &a, &b, &summaries[0]).await?
+    } else {
+        Vec::new()
+    };
 
-    let a = DateData::consume_one(&*conn, a, &mut responses).await;
-    let b = DateData::consume_one(&*conn, b, &mut responses).await;
+    let mut values = values.into_iter();
+    let (_, a_values, b_values) = values.next().unwrap();
+    let mut a = DateData::new(&*conn, &commits, a, a_values).await;
+    let mut b = DateData::new(&*conn, &commits, b, b_values).await;
+    for (stat, a_values, b_values) in values {
+        a.stats.insert(stat.clone(), a_values);
+        b.stats.insert(stat, b_values);
+    }
     Ok(days::Response {
         prev,
         a,
         b,
         next,
         is_contiguous,
+        source_changed,
+        annotations,
+        summaries,
+        query_changes,
     })
 }
 
 impl DateData {
-    async fn consume_one<'a, T>(
-        conn: &dyn database::Connection,
-        commit: ArtifactId,
+    /// Takes the next value of each series, which must be that of `commit`,
+    /// and maps `crate-profile` to the (cache, value) pairs.
+    fn consume_values<T>(
+        commit: &ArtifactId,
         series: &mut [selector::SeriesResponse<T>],
-    ) -> DateData
+    ) -> HashMap<String, Vec<(String, f64)>>
     where
         T: Iterator<Item = (db::ArtifactId, Option<f64>)>,
     {
@@ -707,7 +1394,7 @@ impl DateData {
 
         for response in series {
             let (id, point) = response.series.next().expect("must have element");
-            assert_eq!(commit, id);
+            assert_eq!(*commit, id);
 
             let point = if let Some(pt) = point {
                 pt
@@ -722,8 +1409,17 @@ impl DateData {
             .or_insert_with(Vec::new)
             .push((response.path.get::<Cache>().unwrap().to_string(), point));
         }
+        data
+    }
 
-        let bootstrap = conn.get_bootstrap(&[conn.artifact_id(&commit).await]).await;
+    async fn new(
+        conn: &dyn database::Connection,
+        master_commits: &[database::MasterCommit],
+        commit: ArtifactId,
+        data: HashMap<String, Vec<(String, f64)>>,
+    ) -> DateData {
+        let aid = conn.artifact_id(&commit).await;
+        let bootstrap = conn.get_bootstrap(&[aid]).await;
         let bootstrap = bootstrap
             .into_iter()
             .filter_map(|(k, mut v)| {
@@ -749,7 +1445,6 @@ impl DateData {
                 None
             },
             pr: if let ArtifactId::Commit(c) = &commit {
-                let master_commits = rustc_artifacts::master_commits().await.unwrap_or_default();
                 if let Some(m) = master_commits.iter().find(|m| m.sha == c.sha) {
                     m.pr
                 } else {
@@ -763,7 +1458,9 @@ impl DateData {
                 ArtifactId::Artifact(i) => i,
             },
             data,
+            stats: HashMap::new(),
             bootstrap,
+            compiler: conn.get_compiler_metadata(aid).await,
         }
     }
 }
@@ -779,6 +1476,129 @@ pub async fn handle_collected() -> ServerResult<()> {
     Ok(())
 }
 
+pub async fn handle_queue_artifact(
+    request: queue_artifact::Request,
+    data: &InputData,
+) -> ServerResult<queue_artifact::Response> {
+    if request.name.is_empty() || request.name.contains(char::is_whitespace) {
+        return Err(format!("invalid artifact name {:?}", request.name));
+    }
+    if request.local_rustc.is_none() && !collector::is_published_toolchain(&request.name) {
+        return Err(format!(
+            "{} is not a release, so it needs a local rustc",
+            request.name
+        ));
+    }
+    // Collectors skip benchmarks which already have results, so queueing a
+    // benchmarked artifact again would do nothing; `/perf/invalidate` re-runs it.
+    if data.index.load().artifacts().any(|a| a == request.name) {
+        return Err(format!(
+            "{} has already been benchmarked, invalidate it to re-run it",
+            request.name
+        ));
+    }
+    let queued = data
+        .conn()
+        .await
+        .queue_artifact(&request.name, request.local_rustc.as_deref())
+        .await;
+    Ok(queue_artifact::Response { queued })
+}
+
+/// Deletes the results of an artifact (or of some of its benchmarks) and
+/// marks them as pending, so that the next collector re-runs them.
+pub async fn handle_invalidate(
+    request: invalidate::Request,
+    data: &InputData,
+) -> ServerResult<invalidate::Response> {
+    let mut conn = data.conn().await;
+    let artifact = conn
+        .artifact_by_name(&request.artifact)
+        .await
+        .ok_or_else(|| format!("unknown artifact {}", request.artifact))?;
+    let mut tx = conn.transaction().await;
+    // Queued artifacts are resumed with the toolchain they were queued with.
+    // Others (e.g. published toolchains benchmarked by hand) would never be
+    // benchmarked again, so their results are kept.
+    if let ArtifactId::Artifact(name) = &artifact {
+        if !tx.conn().requeue_artifact(name).await {
+            return Err(format!(
+                "{} was not queued, so it would not be re-run",
+                request.artifact
+            ));
+        }
+    }
+    let aid = tx.conn().artifact_id(&artifact).await;
+    let steps = tx
+        .conn()
+        .invalidate_artifact(aid, request.benchmarks.as_deref())
+        .await;
+    if let Some(benchmarks) = &request.benchmarks {
+        let unknown = benchmarks
+            .iter()
+            .filter(|b| !steps.contains(b))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            return Err(format!(
+                "{} were not benchmarked for {}",
+                unknown.join(", "),
+                request.artifact
+            ));
+        }
+    }
+    tx.commit()
+        .await
+        .map_err(|e| format!("failed to invalidate {}: {:?}", request.artifact, e))?;
+    Ok(invalidate::Response { steps })
+}
+
+pub async fn handle_annotate(
+    request: annotate::Request,
+    data: &InputData,
+) -> ServerResult<annotate::Response> {
+    if request.author.trim().is_empty() || request.text.trim().is_empty() {
+        return Err(String::from("annotations need an author and text"));
+    }
+    let end = request.end.unwrap_or_else(|| request.start.clone());
+    let start_aid = data
+        .data_for(true, Bound::Commit(request.start.clone()))
+        .ok_or_else(|| format!("unknown artifact {}", request.start))?;
+    let end_aid = data
+        .data_for(true, Bound::Commit(end.clone()))
+        .ok_or_else(|| format!("unknown artifact {}", end))?;
+    if let (ArtifactId::Commit(start), ArtifactId::Commit(end)) = (&start_aid, &end_aid) {
+        if start.date > end.date {
+            return Err(format!("{} is older than {}", end.sha, start.sha));
+        }
+    }
+
+    let annotation = database::Annotation {
+        id: 0,
+        start: request.start,
+        end,
+        benchmark: request.benchmark,
+        profile: request.profile,
+        cache: request.cache,
+        author: request.author,
+        text: request.text,
+        created: database::Date(Utc::now()),
+    };
+    let id = data.conn().await.add_annotation(&annotation).await;
+    // The landing page's graph includes the annotations.
+    data.landing_page.store(Arc::new(None));
+    Ok(annotate::Response { id })
+}
+
+pub async fn handle_delete_annotation(
+    request: delete_annotation::Request,
+    data: &InputData,
+) -> ServerResult<delete_annotation::Response> {
+    let deleted = data.conn().await.delete_annotation(request.id).await;
+    data.landing_page.store(Arc::new(None));
+    Ok(delete_annotation::Response { deleted })
+}
+
 fn get_self_profile_data(
     cpu_clock: Option<f64>,
     self_profile: Option<crate::selector::SelfProfileData>,
@@ -879,23 +1699,51 @@ fn get_self_profile_data(
 
 pub async fn handle_self_profile_processed_download(
     body: self_profile_raw::Request,
-    params: HashMap<String, String>,
+    mut params: HashMap<String, String>,
     data: &InputData,
 ) -> Response {
-    let title = format!(
+    // Only the diff is compared against another profile; the other types
+    // ignore the base commit rather than downloading it for nothing.
+    let base_commit = params.remove("base_commit");
+    let base_commit = match params.get("type").map(String::as_str) {
+        Some("flamegraph-diff") => base_commit,
+        _ => None,
+    };
+    let mut title = format!(
         "{}: {} {}",
         &body.commit[..std::cmp::min(7, body.commit.len())],
         body.benchmark,
         body.run_name
     );
+    if let Some(base_commit) = &base_commit {
+        title = format!(
+            "{} vs {}",
+            title,
+            &base_commit[..std::cmp::min(7, base_commit.len())]
+        );
+    }
     let start = Instant::now();
+    let base_pieces = match base_commit {
+        Some(base_commit) => {
+            let base = self_profile_raw::Request {
+                commit: base_commit,
+                cid: None,
+                ..body.clone()
+            };
+            match crate::self_profile::get_pieces(base, data).await {
+                Ok(v) => Some(v),
+                Err(e) => return e,
+            }
+        }
+        None => None,
+    };
     let pieces = match crate::self_profile::get_pieces(body, data).await {
         Ok(v) => v,
         Err(e) => return e,
     };
     log::trace!("got pieces {:?} in {:?}", pieces, start.elapsed());
 
-    let output = match crate::self_profile::generate(&title, pieces, params) {
+    let output = match crate::self_profile::generate(&title, pieces, base_pieces, params) {
         Ok(c) => c,
         Err(e) => {
             log::error!("Failed to generate json {:?}", e);
@@ -905,10 +1753,11 @@ pub async fn handle_self_profile_processed_download(
         }
     };
     let mut builder = http::Response::builder()
-        .header_typed(if output.filename.ends_with("json") {
-            ContentType::json()
-        } else {
-            ContentType::from("image/svg+xml".parse::<mime::Mime>().unwrap())
+        .header_typed(match output.filename.rsplit('.').next() {
+            Some("json") => ContentType::json(),
+            Some("svg") => ContentType::from("image/svg+xml".parse::<mime::Mime>().unwrap()),
+            Some("txt") => ContentType::text_utf8(),
+            _ => ContentType::octet_stream(),
         })
         .status(StatusCode::OK);
 
@@ -923,9 +1772,14 @@ pub async fn handle_self_profile_processed_download(
         );
     }
 
+    // Let the web-based viewers load the profiles they support directly.
     builder.headers_mut().unwrap().insert(
         hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN,
-        hyper::header::HeaderValue::from_static("https://profiler.firefox.com"),
+        hyper::header::HeaderValue::from_static(if output.filename == "speedscope.json" {
+            "https://www.speedscope.app"
+        } else {
+            "https://profiler.firefox.com"
+        }),
     );
 
     builder.body(hyper::Body::from(output.data)).unwrap()
@@ -1499,17 +2353,26 @@ impl Server {
         let data: Arc<InputData> = self.data.read().as_ref().unwrap().clone();
         let _updating = self.updating.release_on_drop(channel);
         let mut conn = data.conn().await;
-        let index = db::Index::load(&mut *conn).await;
-        eprintln!("index has {} commits", index.commits().len());
-        data.index.store(Arc::new(index));
+        let current = data.index.load_full();
+        if let Some(index) = current.refresh(&mut *conn).await {
+            eprintln!("index has {} commits", index.commits().len());
+            data.index.store(Arc::new(index));
+        }
 
         // Refresh the landing page
         data.landing_page.store(Arc::new(None));
 
-        // Spawn off a task to post the results of any commit results that we
-        // are now aware of.
+        // Spawn off a task to compute the aggregate indices of new artifacts,
+        // to post the results of any commit results that we are now aware of,
+        // and to report regressions on master and the results of unrolled
+        // rollups.
         tokio::spawn(async move {
+            data.complete_artifacts().await;
+            data.refresh_master_commits().await;
+            crate::aggregate::update(&data).await;
             crate::github::post_finished(&data).await;
+            crate::github::post_regressions(&data).await;
+            crate::github::post_unrolled(&data).await;
         });
 
         Response::new(body)
@@ -1579,12 +2442,22 @@ async fn serve_req(ctx: Arc<Server>, req: Request) -> Result<Response, ServerErr
             let ret = ctx.handle_get_async(&req, |c| handle_dashboard(c));
             return ret.await;
         }
+        "/perf/aggregate_indices" => {
+            let ret = ctx.handle_get_async(&req, |c| handle_aggregate_indices(c));
+            return ret.await;
+        }
         "/perf/status_page" => {
             let ret = ctx.handle_get_async(&req, |c| handle_status_page(c));
             return ret.await;
         }
+        "/perf/error_history" => {
+            let ret = ctx.handle_get_async(&req, |c| handle_error_history(c));
+            return ret.await;
+        }
         "/perf/next_commit" => {
-            let ret = ctx.handle_get_async(&req, |c| handle_next_commit(c));
+            let collector =
+                query_param(&req, "collector").unwrap_or_else(|| String::from("default"));
+            let ret = ctx.handle_get_async(&req, |c| handle_next_commit(c, collector));
             return ret.await;
         }
         _ => {}
@@ -1645,6 +2518,14 @@ async fn serve_req(ctx: Arc<Server>, req: Request) -> Result<Response, ServerErr
         Ok(to_response(
             handle_graph(body!(parse_body(&body)), &data).await,
         ))
+    } else if p == "/perf/change_points" {
+        Ok(to_response(
+            handle_change_points(body!(parse_body(&body)), &data).await,
+        ))
+    } else if p == "/perf/compare_many" {
+        Ok(to_response(
+            handle_compare_many(body!(parse_body(&body)), &data).await,
+        ))
     } else if p == "/perf/get" {
         Ok(to_response(
             handle_compare(body!(parse_body(&body)), &data).await,
@@ -1657,6 +2538,46 @@ async fn serve_req(ctx: Arc<Server>, req: Request) -> Result<Response, ServerErr
                 .unwrap());
         }
         Ok(to_response(handle_collected().await))
+    } else if p == "/perf/queue_artifact" {
+        if !ctx.check_auth(&req) {
+            return Ok(http::Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(hyper::Body::empty())
+                .unwrap());
+        }
+        Ok(to_response(
+            handle_queue_artifact(body!(parse_body(&body)), &data).await,
+        ))
+    } else if p == "/perf/invalidate" {
+        if !ctx.check_auth(&req) {
+            return Ok(http::Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(hyper::Body::empty())
+                .unwrap());
+        }
+        Ok(to_response(
+            handle_invalidate(body!(parse_body(&body)), &data).await,
+        ))
+    } else if p == "/perf/annotate" {
+        if !ctx.check_auth(&req) {
+            return Ok(http::Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(hyper::Body::empty())
+                .unwrap());
+        }
+        Ok(to_response(
+            handle_annotate(body!(parse_body(&body)), &data).await,
+        ))
+    } else if p == "/perf/delete_annotation" {
+        if !ctx.check_auth(&req) {
+            return Ok(http::Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(hyper::Body::empty())
+                .unwrap());
+        }
+        Ok(to_response(
+            handle_delete_annotation(body!(parse_body(&body)), &data).await,
+        ))
     } else if p == "/perf/github-hook" {
         if !verify_gh(&data.config, &req, &body) {
             return Ok(http::Response::builder()
@@ -1684,6 +2605,10 @@ async fn serve_req(ctx: Arc<Server>, req: Request) -> Result<Response, ServerErr
                 .body(hyper::Body::from(format!("unknown event: {}", event)))
                 .unwrap()),
         }
+    } else if p == "/perf/collector_heartbeat" {
+        Ok(to_response(
+            handle_collector_heartbeat(body!(parse_body(&body)), &data).await,
+        ))
     } else if p == "/perf/self-profile" {
         Ok(to_response(
             handle_self_profile(body!(parse_body(&body)), &data).await,
@@ -1714,6 +2639,35 @@ async fn serve_req(ctx: Arc<Server>, req: Request) -> Result<Response, ServerErr
                     .unwrap(),
             },
         )
+    } else if p == "/perf/query" {
+        let request: query::Request = body!(parse_body(&body));
+        let format = request.format;
+        Ok(match handle_query(request, &data).await {
+            Ok(result) => {
+                let mut response = http::Response::builder()
+                    .header_typed(CacheControl::new().with_no_cache().with_no_store());
+                response.headers_mut().unwrap().insert(
+                    hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                    hyper::header::HeaderValue::from_static("*"),
+                );
+                match format {
+                    query::Format::Json => response
+                        .header_typed(ContentType::json())
+                        .body(hyper::Body::from(serde_json::to_vec(&result).unwrap()))
+                        .unwrap(),
+                    query::Format::Csv => response
+                        .header("Content-Type", "text/csv")
+                        .body(hyper::Body::from(query_csv(&result)))
+                        .unwrap(),
+                }
+            }
+            Err(err) => http::Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header_typed(ContentType::text_utf8())
+                .header_typed(CacheControl::new().with_no_cache().with_no_store())
+                .body(hyper::Body::from(err))
+                .unwrap(),
+        })
     } else if p == "/perf/bootstrap" {
         Ok(
             match handle_bootstrap(body!(parse_body(&body)), &data).await {
@@ -1745,6 +2699,13 @@ async fn serve_req(ctx: Arc<Server>, req: Request) -> Result<Response, ServerErr
     }
 }
 
+/// Returns the value of `key` in the request's query string, if present.
+fn query_param(req: &Request, key: &str) -> Option<String> {
+    url::form_urlencoded::parse(req.uri().query()?.as_bytes())
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+}
+
 fn parse_body<D>(body: &[u8]) -> Result<D, Response>
 where
     D: DeserializeOwned,
@@ -113,6 +113,29 @@ function truncate_name(name) {
     return name;
 }
 
+// Lists annotations (see `database::Annotation`) in the element with the
+// given id.
+function show_annotations(id, annotations) {
+    let element = document.getElementById(id);
+    element.textContent = "";
+    for (let annotation of annotations) {
+        let range = annotation.start.substring(0, 10);
+        if (annotation.end != annotation.start) {
+            range += ".." + annotation.end.substring(0, 10);
+        }
+        let scope = [annotation.benchmark, annotation.profile, annotation.cache]
+            .filter(s => s !== null)
+            .join(" ");
+        if (scope) {
+            range += " (" + scope + ")";
+        }
+        let item = document.createElement("div");
+        // Set as text, as the annotation is free-form.
+        item.textContent = `${range}: ${annotation.text} - ${annotation.author}`;
+        element.appendChild(item);
+    }
+}
+
 function set_date(id, date) {
     let d = new Date(date);
     if (!Number.isNaN(d.getTime())) {
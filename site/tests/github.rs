@@ -0,0 +1,381 @@
+//! End-to-end tests of the rust-timer bot: webhook payloads are fed to
+//! `handle_github`, with GitHub replaced by a `RecordingClient`, and the
+//! resulting queue and comments are checked.
+
+use database::{Pool, QueuedCommit};
+use site::api::github::{
+    Association, Comment, Commit, CommitParent, CommitTree, InnerCommit, Issue, Request, User,
+};
+use site::github::client::RecordingClient;
+use site::github::handle_github;
+use site::load::{Config, InputData, Keys};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const PR: u32 = 1234;
+const OWNER: usize = 1;
+const TEAM_MEMBER: usize = 2;
+const OUTSIDER: usize = 3;
+const BORS: usize = 4;
+
+const TRY_SHA: &str = "1111111111111111111111111111111111111111";
+const PARENT_SHA: &str = "2222222222222222222222222222222222222222";
+
+struct Bot {
+    data: Arc<InputData>,
+    github: Arc<RecordingClient>,
+    path: PathBuf,
+}
+
+impl Bot {
+    async fn new(name: &str) -> Bot {
+        let path = std::env::temp_dir().join(format!(
+            "rustc-perf-github-{}-{}.db",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let github = Arc::new(RecordingClient::new());
+        github.authorize(TEAM_MEMBER);
+        github.add_commit(TRY_SHA, try_commit());
+        let config = Config {
+            keys: Keys {
+                github: Some(String::from("token")),
+                secret: None,
+            },
+            github_api_url: None,
+            aggregate_indices: Vec::new(),
+        };
+        let pool = Pool::open(path.to_str().unwrap());
+        let data = InputData::new(pool, config, github.clone()).await;
+
+        Bot {
+            data: Arc::new(data),
+            github,
+            path,
+        }
+    }
+
+    async fn comment(&self, user: usize, author_association: Association, body: &str) {
+        let request = Request {
+            issue: Issue {
+                number: PR,
+                comments_url: format!(
+                    "https://api.github.com/repos/rust-lang/rust/issues/{}/comments",
+                    PR
+                ),
+                repository_url: String::from("https://api.github.com/repos/rust-lang/rust"),
+            },
+            comment: Comment {
+                html_url: format!("https://github.com/rust-lang/rust/pull/{}#issuecomment", PR),
+                author_association,
+                user: User { id: user },
+                body: body.to_string(),
+            },
+        };
+        handle_github(request, self.data.clone()).await.unwrap();
+    }
+
+    async fn try_build_completed(&self) {
+        let body = format!(
+            ":sunny: Try build successful - [checks-actions](https://example.com)
+Build commit: {sha} (`{sha}`)
+<!-- homu: {{\"type\":\"TryBuildCompleted\",\"merge_sha\":\"{sha}\"}} -->",
+            sha = TRY_SHA
+        );
+        self.comment(BORS, Association::None, &body).await;
+    }
+
+    async fn queued(&self) -> Vec<QueuedCommit> {
+        self.data.conn().await.queued_commits().await
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        for suffix in &["", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn try_commit() -> Commit {
+    Commit {
+        sha: TRY_SHA.to_string(),
+        commit: InnerCommit {
+            message: format!("Auto merge of #{} - try", PR),
+            tree: CommitTree {
+                sha: String::from("3333333333333333333333333333333333333333"),
+            },
+        },
+        parents: vec![
+            CommitParent {
+                sha: PARENT_SHA.to_string(),
+            },
+            CommitParent {
+                sha: String::from("4444444444444444444444444444444444444444"),
+            },
+        ],
+    }
+}
+
+fn queued(include: Option<&str>, exclude: Option<&str>, runs: Option<i32>) -> QueuedCommit {
+    QueuedCommit {
+        pr: PR,
+        sha: TRY_SHA.to_string(),
+        parent_sha: PARENT_SHA.to_string(),
+        include: include.map(String::from),
+        exclude: exclude.map(String::from),
+        runs,
+        builds: None,
+        scenarios: None,
+        high_priority: false,
+    }
+}
+
+#[tokio::test]
+async fn ignores_unrelated_comments() {
+    let bot = Bot::new("unrelated").await;
+    bot.comment(OWNER, Association::Owner, "r=me, rust-timer looks fine")
+        .await;
+
+    assert!(bot.github.requests().is_empty());
+    assert!(bot.queued().await.is_empty());
+}
+
+#[tokio::test]
+async fn rejects_unauthorized_users() {
+    let bot = Bot::new("unauthorized").await;
+    bot.comment(OUTSIDER, Association::Contributor, "@rust-timer queue")
+        .await;
+    assert_eq!(
+        bot.github.comments(PR),
+        vec!["Insufficient permissions to issue commands to rust-timer."]
+    );
+
+    // Nothing was queued, so the try build is not attached.
+    bot.try_build_completed().await;
+    assert!(bot.queued().await.is_empty());
+    assert_eq!(bot.github.comments(PR).len(), 1);
+}
+
+#[tokio::test]
+async fn queue_waits_for_try_build() {
+    let bot = Bot::new("queue").await;
+    bot.comment(
+        OWNER,
+        Association::Owner,
+        "@bors try @rust-timer queue include=syn runs=2",
+    )
+    .await;
+    assert!(bot.github.comments(PR)[0].starts_with("Awaiting bors try build completion."));
+    assert!(bot.queued().await.is_empty());
+
+    bot.try_build_completed().await;
+    assert_eq!(bot.queued().await, vec![queued(Some("syn"), None, Some(2))]);
+    let comments = bot.github.comments(PR);
+    assert_eq!(comments.len(), 2);
+    assert!(comments[1].starts_with(&format!("Queued {} with parent {}", TRY_SHA, PARENT_SHA)));
+}
+
+#[tokio::test]
+async fn build_queues_commit_for_team_members() {
+    let bot = Bot::new("build").await;
+    bot.comment(
+        TEAM_MEMBER,
+        Association::Member,
+        &format!("@rust-timer build {} exclude=regex", TRY_SHA),
+    )
+    .await;
+
+    assert_eq!(bot.queued().await, vec![queued(None, Some("regex"), None)]);
+    let comments = bot.github.comments(PR);
+    assert_eq!(comments.len(), 1);
+    assert!(comments[0].starts_with(&format!("Queued {}", TRY_SHA)));
+}
+
+#[tokio::test]
+async fn try_build_without_request_is_ignored() {
+    let bot = Bot::new("unrequested").await;
+    bot.try_build_completed().await;
+
+    assert!(bot.queued().await.is_empty());
+    assert!(bot.github.requests().is_empty());
+}
+
+#[tokio::test]
+async fn priority_is_recorded() {
+    let bot = Bot::new("priority").await;
+    bot.comment(
+        OWNER,
+        Association::Owner,
+        &format!("@rust-timer build {} runs=1 priority=high", TRY_SHA),
+    )
+    .await;
+
+    let expected = QueuedCommit {
+        high_priority: true,
+        ..queued(None, None, Some(1))
+    };
+    assert_eq!(bot.queued().await, vec![expected]);
+}
+
+#[tokio::test]
+async fn builds_and_scenarios_are_recorded() {
+    let bot = Bot::new("selection").await;
+    bot.comment(
+        OWNER,
+        Association::Owner,
+        &format!(
+            "@rust-timer build {} builds=Check,Debug scenarios=IncrPatched",
+            TRY_SHA
+        ),
+    )
+    .await;
+
+    let expected = QueuedCommit {
+        builds: Some(String::from("Check,Debug")),
+        scenarios: Some(String::from("IncrPatched")),
+        ..queued(None, None, None)
+    };
+    assert_eq!(bot.queued().await, vec![expected]);
+}
+
+#[tokio::test]
+async fn unknown_build_kinds_are_rejected() {
+    let bot = Bot::new("bad-selection").await;
+    bot.comment(
+        OWNER,
+        Association::Owner,
+        "@bors try @rust-timer queue builds=Check,Release",
+    )
+    .await;
+
+    assert!(bot.github.comments(PR)[0].starts_with("`Release` is not a known build kind"));
+    bot.try_build_completed().await;
+    assert!(bot.queued().await.is_empty());
+}
+
+#[tokio::test]
+async fn cancel_removes_queued_build() {
+    let bot = Bot::new("cancel").await;
+    bot.comment(
+        TEAM_MEMBER,
+        Association::Member,
+        &format!("@rust-timer build {}", TRY_SHA),
+    )
+    .await;
+    assert_eq!(bot.queued().await.len(), 1);
+
+    bot.comment(TEAM_MEMBER, Association::Member, "@rust-timer cancel")
+        .await;
+    assert!(bot.queued().await.is_empty());
+    bot.comment(TEAM_MEMBER, Association::Member, "@rust-timer cancel")
+        .await;
+
+    let comments = bot.github.comments(PR);
+    assert_eq!(comments.len(), 3);
+    assert!(comments[1].starts_with("Cancelled the queued perf run."));
+    assert_eq!(comments[2], "There is no queued perf run to cancel.");
+}
+
+#[tokio::test]
+async fn cancel_requires_authorization() {
+    let bot = Bot::new("cancel-unauthorized").await;
+    bot.comment(
+        OWNER,
+        Association::Owner,
+        &format!("@rust-timer build {}", TRY_SHA),
+    )
+    .await;
+    bot.comment(OUTSIDER, Association::Contributor, "@rust-timer cancel")
+        .await;
+
+    assert_eq!(bot.queued().await, vec![queued(None, None, None)]);
+    assert_eq!(
+        bot.github.comments(PR)[1],
+        "Insufficient permissions to issue commands to rust-timer."
+    );
+}
+
+fn commit(sha: &str, message: &str, parents: &[&str]) -> Commit {
+    Commit {
+        sha: sha.to_string(),
+        commit: InnerCommit {
+            message: message.to_string(),
+            tree: CommitTree {
+                sha: format!("tree-of-{}", sha),
+            },
+        },
+        parents: parents
+            .iter()
+            .map(|p| CommitParent { sha: p.to_string() })
+            .collect(),
+    }
+}
+
+#[tokio::test]
+async fn unroll_compares_first_member_against_master_before_rollup() {
+    let bot = Bot::new("unroll").await;
+    let (base, first, second, rollup) = ("base", "rollup-first", "rollup-second", "rollup");
+    bot.github.add_commit(
+        base,
+        commit(base, "Auto merge of #1 - earlier", &["a", "b"]),
+    );
+    bot.github.add_commit(
+        first,
+        commit(first, "Rollup merge of #11 - first", &[base, "c"]),
+    );
+    bot.github.add_commit(
+        second,
+        commit(second, "Rollup merge of #12 - second", &[first, "d"]),
+    );
+    bot.github.add_commit(
+        rollup,
+        commit(rollup, "Auto merge of #1234 - rollup", &[base, second]),
+    );
+    bot.github.add_commit(
+        "master",
+        commit("master", "Auto merge of #2 - later", &[rollup, "e"]),
+    );
+
+    bot.comment(OWNER, Association::Owner, "@rust-timer unroll rollup")
+        .await;
+    let members = bot.data.conn().await.unreported_rollup_members().await;
+    assert_eq!(
+        members
+            .iter()
+            .map(|m| (m.rollup_pr, m.position, m.rolled_up_pr))
+            .collect::<Vec<_>>(),
+        vec![(PR, 0, 11), (PR, 1, 12)]
+    );
+    assert!(members.iter().all(|m| m.base_sha.as_deref() == Some(base)));
+
+    // The try builds are based on master at the time they ran.
+    for (member, sha) in members.iter().zip(&["try-first", "try-second"]) {
+        let conn = bot.data.conn().await;
+        conn.queue_pr(member.perf_pr, None, None, None, None, None, false)
+            .await;
+        conn.pr_attach_commit(member.perf_pr, sha, "master").await;
+        conn.mark_complete(sha).await;
+    }
+    site::github::post_unrolled(&bot.data).await;
+
+    let summary = bot.github.comments(PR).pop().unwrap();
+    assert!(summary.contains("start=base&end=try-first"), "{}", summary);
+    assert!(
+        summary.contains("start=try-first&end=try-second"),
+        "{}",
+        summary
+    );
+    assert!(bot
+        .data
+        .conn()
+        .await
+        .unreported_rollup_members()
+        .await
+        .is_empty());
+}